@@ -0,0 +1,543 @@
+//! Renders configuration errors with their position in the document.
+//!
+//! Documents may hold secrets, so string contents are masked in the rendered
+//! line and the offending string is masked in the error message.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    ops::Range,
+};
+
+use toml_edit::{ImDocument, Item, TableLike, Value};
+
+use super::Error;
+
+/// Locates a parse error within its configuration document.
+#[derive(Clone, Default)]
+pub struct Diagnostic {
+    /// Holds the dotted key path of the offending entry, if known.
+    key: Option<String>,
+
+    /// Holds the offending line, if the error carries a span.
+    position: Option<Box<Position>>,
+}
+
+/// Identifies the offending part of a document line.
+#[derive(Clone)]
+struct Position {
+    /// Provides the 1-based line number.
+    line: usize,
+
+    /// Provides the 1-based column in characters.
+    column: usize,
+
+    /// Holds the text of the line with string contents masked.
+    text: String,
+
+    /// Counts the underlined characters.
+    width: usize,
+
+    /// Holds the error message with the offending string masked.
+    message: String,
+}
+
+impl Diagnostic {
+    /// Locates `error` within `document`.
+    pub(super) fn new(document: &str, error: &toml::de::Error) -> Self {
+        let Some(span) = error.span() else {
+            return Self::default();
+        };
+
+        let parsed = ImDocument::parse(document).ok();
+        let mut strings = Vec::new();
+        if let Some(parsed) = &parsed {
+            table_strings(parsed.as_table(), &mut strings);
+        }
+
+        let mut message = error.message().to_owned();
+        let mut masks = Vec::new();
+        for (range, value) in &strings {
+            if range.start < span.end && span.start < range.end {
+                message = message.replace(&format!("{value:?}"), &format!("{MASK:?}"));
+            }
+            masks.push(contents(document, range.clone()));
+        }
+        if parsed.is_none() {
+            // Without a syntax tree, mask everything after the first quote.
+            let line_start = document[..span.start.min(document.len())]
+                .rfind('\n')
+                .map_or(0, |index| index + 1);
+            let line = document[line_start..].lines().next().unwrap_or_default();
+            if let Some(quote) = line.find(['\'', '"']) {
+                masks.push(line_start + quote + 1..line_start + line.len());
+            }
+        }
+
+        let mut key = parsed
+            .as_ref()
+            .and_then(|parsed| key_path(parsed, span.start));
+        if let Some(field) = quoted(error.message(), "missing field ") {
+            key = Some(match key {
+                Some(key) => format!("{key}.{}", bare_or_quoted(field)),
+                None => bare_or_quoted(field),
+            });
+        }
+
+        Self {
+            key,
+            position: Some(Box::new(Position::new(document, span, &masks, message))),
+        }
+    }
+
+    /// Returns the dotted key path of the offending entry, such as
+    /// `database.pool_size`, if known.
+    #[must_use]
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// Returns the 1-based line and column of the offending value, if known.
+    #[must_use]
+    pub fn line_column(&self) -> Option<(usize, usize)> {
+        self.position
+            .as_ref()
+            .map(|position| (position.line, position.column))
+    }
+}
+
+impl Debug for Diagnostic {
+    /// Formats the location without the document text, which may hold secrets.
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Diagnostic")
+            .field("key", &self.key)
+            .field("line_column", &self.line_column())
+            .finish()
+    }
+}
+
+impl Position {
+    /// Describes the line containing the start of `span`, replacing the parts
+    /// of the line covered by `masks` with [`MASK`].
+    fn new(document: &str, span: Range<usize>, masks: &[Range<usize>], message: String) -> Self {
+        let start = span.start.min(document.len());
+        let line_start = document[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = document[start..]
+            .find('\n')
+            .map_or(document.len(), |index| start + index);
+        let line_end = line_start + document[line_start..line_end].trim_end_matches('\r').len();
+        let end = span.end.clamp(start, line_end);
+
+        let mut masks: Vec<_> = masks
+            .iter()
+            .map(|mask| mask.start.max(line_start)..mask.end.min(line_end))
+            .filter(|mask| mask.start < mask.end)
+            .collect();
+        masks.sort_by_key(|mask| mask.start);
+        masks.push(line_end..line_end);
+
+        let mut text = String::new();
+        let (mut column, mut end_column) = (None, None);
+        let mut offset = line_start;
+        for mask in masks {
+            let visible = offset..mask.start.max(offset);
+            for (position, found) in [(start, &mut column), (end, &mut end_column)] {
+                if (visible.start..=visible.end).contains(&position) {
+                    found.get_or_insert(
+                        text.chars().count() + document[offset..position].chars().count(),
+                    );
+                }
+            }
+            text.push_str(&document[visible.clone()]);
+
+            if visible.end < mask.end {
+                if (visible.end..mask.end).contains(&start) {
+                    column.get_or_insert(text.chars().count());
+                }
+                text.push_str(MASK);
+                if (visible.end..=mask.end).contains(&end) {
+                    end_column.get_or_insert(text.chars().count());
+                }
+            }
+            offset = mask.end.max(offset);
+        }
+
+        let column = column.unwrap_or_default();
+        Self {
+            line: document[..start].matches('\n').count() + 1,
+            column: column + 1,
+            text,
+            width: end_column.unwrap_or(column).saturating_sub(column).max(1),
+            message,
+        }
+    }
+}
+
+/// Replaces masked string contents.
+const MASK: &str = "***";
+
+/// Returns the contents of the string spanning `range`, without its quotes.
+fn contents(document: &str, range: Range<usize>) -> Range<usize> {
+    let quoted = &document[range.clone()];
+    let quotes = if quoted.starts_with("\"\"\"") || quoted.starts_with("'''") {
+        3
+    } else {
+        1
+    };
+    if quoted.len() < 2 * quotes {
+        return range.start..range.start;
+    }
+
+    range.start + quotes..range.end - quotes
+}
+
+/// Renders `error` for display on a terminal.
+pub(super) fn render(error: &Error) -> String {
+    let mut rendered = format!("error: {error}\n");
+    let (message, diagnostic) = match error {
+        Error::Parse {
+            location,
+            source,
+            diagnostic,
+        } => {
+            let message = diagnostic
+                .position
+                .as_ref()
+                .map_or(source.message(), |position| &position.message);
+            match &diagnostic.position {
+                Some(position) => {
+                    let gutter = position.line.to_string().len();
+                    rendered.push_str(&format!(
+                        "{:gutter$}--> {location}:{}:{}\n",
+                        "", position.line, position.column
+                    ));
+                    rendered.push_str(&format!("{:gutter$} |\n", ""));
+                    rendered.push_str(&format!("{} | {}\n", position.line, position.text));
+                    rendered.push_str(&format!(
+                        "{:gutter$} | {:indent$}{} {}\n",
+                        "",
+                        "",
+                        "^".repeat(position.width),
+                        message,
+                        indent = position.column - 1,
+                    ));
+                }
+                None => rendered.push_str(&format!("  = note: {message}\n")),
+            }
+            (message, Some(diagnostic))
+        }
+        Error::ParseEnvironment { source, .. } => {
+            rendered.push_str(&format!("  = note: {}\n", source.message()));
+            (source.message(), None)
+        }
+        _ => {
+            let mut source = std::error::Error::source(error);
+            while let Some(error) = source {
+                rendered.push_str(&format!("  caused by: {error}\n"));
+                source = error.source();
+            }
+            return rendered;
+        }
+    };
+
+    if let Some(key) = diagnostic.and_then(Diagnostic::key) {
+        rendered.push_str(&format!("  = key: {key}\n"));
+    }
+    if let Some((_, expected)) = message.split_once(", expected ") {
+        rendered.push_str(&format!("  = expected: {expected}\n"));
+    }
+    if let Some(suggestion) = suggest(message) {
+        rendered.push_str(&format!("  = help: did you mean `{suggestion}`?\n"));
+    }
+
+    rendered
+}
+
+/// Suggests the closest expected name for an unknown field or variant.
+fn suggest(message: &str) -> Option<&str> {
+    let (unknown, expected) = message.split_once(", expected ")?;
+    let unknown =
+        quoted(unknown, "unknown field ").or_else(|| quoted(unknown, "unknown variant "))?;
+    let threshold = (unknown.chars().count() / 3).max(1);
+
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|candidate| (distance(unknown, candidate), candidate))
+        .filter(|&(distance, _)| distance <= threshold)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Returns the backquoted name following `prefix` in `message`.
+fn quoted<'a>(message: &'a str, prefix: &str) -> Option<&'a str> {
+    message
+        .strip_prefix(prefix)?
+        .strip_prefix('`')?
+        .split('`')
+        .next()
+}
+
+/// Computes the Levenshtein distance between two names.
+fn distance(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+    for (row, left) in left.chars().enumerate() {
+        let mut current = vec![row + 1];
+        for (column, &right) in right.iter().enumerate() {
+            let substitution = previous[column] + usize::from(left != right);
+            current.push(
+                substitution
+                    .min(previous[column + 1] + 1)
+                    .min(current[column] + 1),
+            );
+        }
+        previous = current;
+    }
+
+    previous[right.len()]
+}
+
+/// Finds the dotted key path of the entry containing `offset`.
+fn key_path(document: &ImDocument<&str>, offset: usize) -> Option<String> {
+    let mut path = Vec::new();
+
+    find_in_table(document.as_table(), offset, &mut path).then(|| {
+        path.iter().fold(String::new(), |mut joined, segment| {
+            if !joined.is_empty() && !segment.starts_with('[') {
+                joined.push('.');
+            }
+            joined.push_str(segment);
+            joined
+        })
+    })
+}
+
+/// Appends the path to `offset` within `table` to `path`.
+fn find_in_table(table: &dyn TableLike, offset: usize, path: &mut Vec<String>) -> bool {
+    for (name, item) in table.iter() {
+        let key = table.get_key_value(name).map(|(key, _)| key);
+        path.push(bare_or_quoted(name));
+        if find_in_item(item, offset, path) || contains(key.and_then(|key| key.span()), offset) {
+            return true;
+        }
+        path.pop();
+    }
+
+    false
+}
+
+/// Appends the path to `offset` within `item` to `path`.
+fn find_in_item(item: &Item, offset: usize, path: &mut Vec<String>) -> bool {
+    match item {
+        Item::None => false,
+        Item::Value(value) => find_in_value(value, offset, path),
+        Item::Table(table) => find_in_table(table, offset, path) || contains(table.span(), offset),
+        Item::ArrayOfTables(array) => {
+            for (index, table) in array.iter().enumerate() {
+                path.push(format!("[{index}]"));
+                if find_in_table(table, offset, path) || contains(table.span(), offset) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+    }
+}
+
+/// Appends the path to `offset` within `value` to `path`.
+fn find_in_value(value: &Value, offset: usize, path: &mut Vec<String>) -> bool {
+    match value {
+        Value::InlineTable(table) if find_in_table(table, offset, path) => return true,
+        Value::Array(array) => {
+            for (index, element) in array.iter().enumerate() {
+                path.push(format!("[{index}]"));
+                if find_in_value(element, offset, path) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+
+    contains(value.span(), offset)
+}
+
+/// Collects the spans and values of the strings within `table`.
+fn table_strings(table: &dyn TableLike, strings: &mut Vec<(Range<usize>, String)>) {
+    for (_, item) in table.iter() {
+        match item {
+            Item::None => {}
+            Item::Value(value) => value_strings(value, strings),
+            Item::Table(table) => table_strings(table, strings),
+            Item::ArrayOfTables(array) => {
+                for table in array.iter() {
+                    table_strings(table, strings);
+                }
+            }
+        }
+    }
+}
+
+/// Collects the spans and values of the strings within `value`.
+fn value_strings(value: &Value, strings: &mut Vec<(Range<usize>, String)>) {
+    match value {
+        Value::String(string) => {
+            if let Some(span) = string.span() {
+                strings.push((span, string.value().clone()));
+            }
+        }
+        Value::Array(array) => {
+            for element in array.iter() {
+                value_strings(element, strings);
+            }
+        }
+        Value::InlineTable(table) => table_strings(table, strings),
+        _ => {}
+    }
+}
+
+/// Reports whether `span` contains `offset`.
+fn contains(span: Option<Range<usize>>, offset: usize) -> bool {
+    span.is_some_and(|span| span.contains(&offset))
+}
+
+/// Quotes a key unless it may be written bare.
+fn bare_or_quoted(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '-' | '_'));
+    if bare {
+        key.to_owned()
+    } else {
+        format!("{key:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::{distance, suggest};
+    use crate::config::{deserialize, Error, Location};
+
+    /// Provides a nested table.
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Database {
+        /// Provides a required string.
+        url: String,
+
+        /// Provides a typed field.
+        pool_size: u32,
+    }
+
+    /// Provides a document with nested and listed values.
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Config {
+        /// Provides a nested table.
+        database: Database,
+
+        /// Provides a list of tables.
+        #[serde(default)]
+        workers: Vec<Database>,
+    }
+
+    /// Renders the error of parsing `document`.
+    fn render(document: &str) -> String {
+        deserialize::<Config>(document, Location::StandardInput)
+            .expect_err("document should be invalid")
+            .render()
+    }
+
+    /// Shows the offending line, key path and expected type.
+    #[test]
+    fn renders_invalid_values() {
+        assert_eq!(
+            render("[database]\nurl = 'x'\npool_size = 'ten'\n"),
+            concat!(
+                "error: failed to parse configuration from standard input\n",
+                " --> standard input:3:13\n",
+                "  |\n",
+                "3 | pool_size = '***'\n",
+                "  |             ^^^^^ invalid type: string \"***\", expected u32\n",
+                "  = key: database.pool_size\n",
+                "  = expected: u32\n",
+            )
+        );
+
+        let rendered = render(concat!(
+            "database = { url = 'x', pool_size = 1 }\n",
+            "[[workers]]\nurl = 'y'\npool_size = -1\n",
+        ));
+        assert!(rendered.contains("  = key: workers[0].pool_size\n"));
+    }
+
+    /// Masks string contents in the rendered line and message.
+    #[test]
+    fn masks_strings() {
+        let rendered = render(concat!(
+            "[database]\n",
+            "url = 'mysql://app:hunter2@db/app'\n",
+            "pool_size = 'hunter2'\n",
+        ));
+        assert!(!rendered.contains("hunter2"), "{rendered}");
+        assert!(rendered.contains("3 | pool_size = '***'\n"), "{rendered}");
+        assert!(rendered.contains("invalid type: string \"***\", expected u32"));
+
+        let rendered =
+            render("database = { url = 'mysql://app:hunter2@db/app', pool_size = '''ten''' }\n");
+        assert!(!rendered.contains("hunter2"), "{rendered}");
+        assert!(
+            rendered.contains(concat!(
+                "1 | database = { url = '***', pool_size = '''***''' }\n",
+                "  |                                       ^^^^^^^^^ ",
+            )),
+            "{rendered}"
+        );
+
+        let rendered = render("[database]\nurl = 'mysql://app:hunter2@db/app\n");
+        assert!(!rendered.contains("hunter2"), "{rendered}");
+        assert!(rendered.contains("2 | url = '***\n"), "{rendered}");
+    }
+
+    /// Suggests the closest field name and names missing fields.
+    #[test]
+    fn renders_unknown_and_missing_fields() {
+        let rendered = render("[database]\nurl = 'x'\npol_size = 1\n");
+        assert!(rendered.contains("  = key: database.pol_size\n"));
+        assert!(rendered.contains("  = help: did you mean `pool_size`?\n"));
+
+        let rendered = render("[database]\npool_size = 1\n");
+        assert!(rendered.contains("  = key: database.url\n"));
+    }
+
+    /// Renders errors without a document position as a cause chain.
+    #[test]
+    fn renders_other_errors() {
+        assert_eq!(
+            Error::MissingPath.render(),
+            "error: configuration file path is required\n"
+        );
+    }
+
+    /// Suggests only sufficiently similar names.
+    #[test]
+    fn suggests_similar_names() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(
+            suggest("unknown variant `jsn`, expected one of `auto`, `json`, `logfmt`"),
+            Some("json")
+        );
+        assert_eq!(
+            suggest("unknown field `colour`, expected `url` or `pool_size`"),
+            None
+        );
+    }
+}
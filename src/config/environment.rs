@@ -0,0 +1,347 @@
+//! Layers prefixed environment variables over a configuration document.
+//!
+//! A variable named `{PREFIX}_{KEY}` overrides `key`, with `__` separating
+//! nested tables. Values are read as TOML values when they parse as one and as
+//! strings otherwise.
+
+use std::{collections::BTreeMap, ffi::OsString};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use toml::{de::ValueDeserializer, Table, Value};
+
+use super::{deserialize, Error, Location};
+
+/// Holds configuration overrides collected from the environment.
+#[derive(Debug)]
+pub(super) struct Overrides {
+    /// Maps each overridden key path to the winning variable and its value.
+    entries: BTreeMap<Vec<String>, Override>,
+}
+
+/// Holds a single environment override.
+#[derive(Debug)]
+struct Override {
+    /// Names the variable that supplied the value.
+    variable: String,
+
+    /// Provides the parsed value.
+    value: Value,
+}
+
+impl Overrides {
+    /// Collects overrides for `prefix` from the process environment.
+    pub(super) fn from_env(prefix: &str) -> Result<Self, Error> {
+        Self::from_variables(prefix, std::env::vars_os())
+    }
+
+    /// Collects overrides for `prefix` from the supplied variables.
+    ///
+    /// Variables are applied in name order, so the result does not depend on
+    /// the iteration order of the environment.
+    pub(super) fn from_variables<I>(prefix: &str, variables: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (OsString, OsString)>,
+    {
+        let prefix = format!("{prefix}_");
+        let mut variables: Vec<_> = variables
+            .into_iter()
+            .filter(|(name, _)| name.to_string_lossy().starts_with(&prefix))
+            .collect();
+        variables.sort();
+
+        let mut entries = BTreeMap::new();
+        for (name, value) in variables {
+            let variable = name.to_string_lossy().into_owned();
+            let path = variable[prefix.len()..]
+                .split("__")
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>();
+            if path.iter().any(String::is_empty) {
+                return Err(Error::Environment { variable });
+            }
+            let Some(value) = value.to_str().map(parse_value) else {
+                return Err(Error::Environment { variable });
+            };
+
+            entries.insert(path, Override { variable, value });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Reports whether no variable matched the prefix.
+    pub(super) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the document with every override applied.
+    fn apply(&self, mut document: Table) -> Table {
+        for (path, entry) in &self.entries {
+            insert(&mut document, path, entry.value.clone());
+        }
+        document
+    }
+
+    /// Names the variable that supplied the value rejected by `error`.
+    ///
+    /// Each override is reverted in turn; an override is blamed when reverting
+    /// it changes or resolves the failure. Value errors are reported before
+    /// missing fields, so reverting an unrelated override leaves the failure
+    /// unchanged. Returns `None` when the document is to blame.
+    fn attribute<T>(
+        &self,
+        document: &Table,
+        merged: &Table,
+        error: &toml::de::Error,
+    ) -> Option<&str>
+    where
+        T: DeserializeOwned,
+    {
+        for (path, entry) in &self.entries {
+            let mut reverted = merged.clone();
+            match lookup(document, path) {
+                Some(value) => insert(&mut reverted, path, value.clone()),
+                None => remove(&mut reverted, path),
+            }
+
+            match Value::Table(reverted).try_into::<T>() {
+                Err(reverted) if reverted.message() == error.message() => {}
+                _ => return Some(&entry.variable),
+            }
+        }
+
+        None
+    }
+}
+
+/// Deserializes a TOML document after applying environment overrides.
+pub(super) fn deserialize_with_env<T>(
+    serialized: &str,
+    location: Location,
+    overrides: &Overrides,
+) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    if overrides.is_empty() {
+        return deserialize(serialized, location);
+    }
+
+    let document: Table = match toml::from_str(serialized) {
+        Ok(document) => document,
+        Err(source) => return Err(Error::parse(location, serialized, source)),
+    };
+    let merged = overrides.apply(document.clone());
+
+    let error = match Value::Table(merged.clone()).try_into() {
+        Ok(config) => return Ok(config),
+        Err(error) => error,
+    };
+
+    match overrides.attribute::<T>(&document, &merged, &error) {
+        Some(variable) => Err(Error::ParseEnvironment {
+            variable: variable.to_owned(),
+            source: error,
+        }),
+        None => {
+            // Prefer the document's own error, which carries a source span.
+            let source = match toml::from_str::<T>(serialized) {
+                Err(original) if original.message() == error.message() => original,
+                _ => error,
+            };
+            Err(Error::parse(location, serialized, source))
+        }
+    }
+}
+
+/// Parses a variable as a TOML value, falling back to a plain string.
+fn parse_value(raw: &str) -> Value {
+    Value::deserialize(ValueDeserializer::new(raw))
+        .unwrap_or_else(|_| Value::String(raw.to_owned()))
+}
+
+/// Returns the value at a key path.
+fn lookup<'a>(table: &'a Table, path: &[String]) -> Option<&'a Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(key)?.as_table()?;
+    }
+    table.get(last)
+}
+
+/// Inserts a value at a key path, replacing non-table parents with tables.
+fn insert(table: &mut Table, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut table = table;
+    for key in parents {
+        let parent = table
+            .entry(key.as_str())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !parent.is_table() {
+            *parent = Value::Table(Table::new());
+        }
+        table = match parent {
+            Value::Table(parent) => parent,
+            _ => unreachable!("parent was just replaced with a table"),
+        };
+    }
+    table.insert(last.clone(), value);
+}
+
+/// Removes the value at a key path if present.
+fn remove(table: &mut Table, path: &[String]) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut table = table;
+    for key in parents {
+        table = match table.get_mut(key) {
+            Some(Value::Table(parent)) => parent,
+            _ => return,
+        };
+    }
+    table.remove(last);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, path::PathBuf};
+
+    use serde::Deserialize;
+
+    use super::{deserialize_with_env, Overrides};
+    use crate::config::{Core, Error, ListenAddress, Location};
+
+    /// Provides nested application configuration around shared configuration.
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        /// Provides shared web application configuration.
+        #[serde(flatten)]
+        core: Core,
+
+        /// Provides a nested application table.
+        database: Database,
+    }
+
+    /// Provides a nested table for override tests.
+    #[derive(Debug, Deserialize)]
+    struct Database {
+        /// Sizes a hypothetical connection pool.
+        pool_size: u32,
+    }
+
+    /// Collects overrides from literal variable pairs.
+    fn overrides(variables: &[(&str, &str)]) -> Overrides {
+        Overrides::from_variables(
+            "MYAPP",
+            variables
+                .iter()
+                .map(|(name, value)| (OsString::from(name), OsString::from(value))),
+        )
+        .expect("variables should be valid overrides")
+    }
+
+    /// Overrides top-level and nested keys from prefixed variables.
+    #[test]
+    fn overrides_document_values() {
+        let overrides = overrides(&[
+            ("MYAPP_LISTEN_ADDRESS", "/run/myapp/http.sock"),
+            ("MYAPP_DATABASE__POOL_SIZE", "16"),
+            ("OTHER_LISTEN_ADDRESS", "[::1]:3000"),
+        ]);
+        let config: Config = deserialize_with_env(
+            concat!(
+                "listen_address = '127.0.0.1:3000'\n",
+                "[database]\n",
+                "pool_size = 4\n",
+            ),
+            Location::File(PathBuf::from("test")),
+            &overrides,
+        )
+        .expect("configuration should deserialize");
+
+        assert_eq!(
+            config.core.listen_address.as_slice(),
+            [ListenAddress::Unix(PathBuf::from("/run/myapp/http.sock"))]
+        );
+        assert_eq!(config.database.pool_size, 16);
+    }
+
+    /// Supplies keys that are missing from the document.
+    #[test]
+    fn supplies_missing_keys() {
+        let config: Config = deserialize_with_env(
+            "listen_address = '127.0.0.1:3000'\n",
+            Location::StandardInput,
+            &overrides(&[("MYAPP_DATABASE__POOL_SIZE", "2")]),
+        )
+        .expect("configuration should deserialize");
+
+        assert_eq!(config.database.pool_size, 2);
+    }
+
+    /// Blames the environment variable that supplied an invalid value.
+    #[test]
+    fn attributes_invalid_values_to_variables() {
+        let error = deserialize_with_env::<Config>(
+            concat!(
+                "listen_address = '127.0.0.1:3000'\n",
+                "[database]\n",
+                "pool_size = 4\n",
+            ),
+            Location::StandardInput,
+            &overrides(&[
+                ("MYAPP_LISTEN_ADDRESS", "[::1]:3000"),
+                ("MYAPP_DATABASE__POOL_SIZE", "many"),
+            ]),
+        )
+        .expect_err("configuration should be rejected");
+
+        assert!(matches!(
+            error,
+            Error::ParseEnvironment { ref variable, .. } if variable == "MYAPP_DATABASE__POOL_SIZE"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "failed to parse configuration from environment variable MYAPP_DATABASE__POOL_SIZE"
+        );
+    }
+
+    /// Blames the document when an override is unrelated to the failure.
+    #[test]
+    fn attributes_invalid_values_to_document() {
+        let error = deserialize_with_env::<Config>(
+            concat!(
+                "listen_address = 'localhost:3000'\n",
+                "[database]\n",
+                "pool_size = 4\n",
+            ),
+            Location::File(PathBuf::from("app.toml")),
+            &overrides(&[("MYAPP_DATABASE__POOL_SIZE", "8")]),
+        )
+        .expect_err("configuration should be rejected");
+
+        assert!(matches!(error, Error::Parse { .. }));
+        assert_eq!(
+            error.to_string(),
+            "failed to parse configuration from app.toml"
+        );
+    }
+
+    /// Rejects variables that do not name a key.
+    #[test]
+    fn rejects_empty_key_segments() {
+        let result = Overrides::from_variables(
+            "MYAPP",
+            [(
+                OsString::from("MYAPP_DATABASE____POOL_SIZE"),
+                OsString::from("1"),
+            )],
+        );
+
+        assert!(matches!(result, Err(Error::Environment { .. })));
+    }
+}
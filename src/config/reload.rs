@@ -0,0 +1,273 @@
+//! Re-reads configuration on `SIGHUP` and publishes each accepted value.
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use serde::de::DeserializeOwned;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+use tracing::{error, info};
+
+use super::{command_from_args_or_check, load_location_prefixed, Error, Location};
+
+/// Holds a configuration value that can be reloaded from its source.
+///
+/// The value is published through a [`watch`] channel, so tasks holding a
+/// [`Reloader::subscribe`] receiver observe each accepted reload. A reload that
+/// fails to read or deserialize leaves the previous value in place. Clones
+/// share the same source and value.
+#[derive(Debug)]
+pub struct Reloader<T> {
+    /// Holds the state shared by all clones.
+    inner: Arc<Inner<T>>,
+}
+
+/// Holds the state shared by [`Reloader`] clones.
+#[derive(Debug)]
+struct Inner<T> {
+    /// Identifies the document read at startup.
+    location: Location,
+
+    /// Selects environment overrides, if they were requested at startup.
+    prefix: Option<String>,
+
+    /// Publishes the most recently accepted value.
+    current: watch::Sender<Arc<T>>,
+
+    /// Records whether the `SIGHUP` handler has been installed.
+    listening: AtomicBool,
+}
+
+impl<T> Clone for Reloader<T> {
+    /// Returns another handle to the same configuration.
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> Reloader<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    /// Loads configuration from the sole process argument.
+    ///
+    /// See [`from_args`](super::from_args).
+    pub fn from_args() -> Result<Self, Error> {
+        Self::new(
+            command_from_args_or_check::<T>(false, None)?.into_config(),
+            None,
+        )
+    }
+
+    /// Loads configuration from the sole process argument with environment
+    /// overrides.
+    ///
+    /// See [`from_args_with_env`](super::from_args_with_env).
+    pub fn from_args_with_env(prefix: &str) -> Result<Self, Error> {
+        Self::new(
+            command_from_args_or_check::<T>(false, Some(prefix))?.into_config(),
+            Some(prefix.to_owned()),
+        )
+    }
+
+    /// Loads configuration from a TOML file or standard input.
+    ///
+    /// See [`load`](super::load).
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::new(path.to_owned().into(), None)
+    }
+
+    /// Loads configuration from a TOML file or standard input with
+    /// environment overrides.
+    ///
+    /// See [`load_with_env`](super::load_with_env).
+    pub fn load_with_env(path: &Path, prefix: &str) -> Result<Self, Error> {
+        Self::new(path.to_owned().into(), Some(prefix.to_owned()))
+    }
+
+    /// Loads the initial value and remembers its source.
+    fn new(location: Location, prefix: Option<String>) -> Result<Self, Error> {
+        let value = load_location_prefixed(location.clone(), prefix.as_deref())?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                location,
+                prefix,
+                current: watch::Sender::new(Arc::new(value)),
+                listening: AtomicBool::new(false),
+            }),
+        })
+    }
+
+    /// Returns the most recently accepted value.
+    #[must_use]
+    pub fn current(&self) -> Arc<T> {
+        Arc::clone(&self.inner.current.borrow())
+    }
+
+    /// Returns a receiver that observes every accepted reload.
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.inner.current.subscribe()
+    }
+
+    /// Re-reads the configuration source and publishes the new value.
+    ///
+    /// On failure the previous value remains current. Configuration read from
+    /// standard input cannot be reloaded.
+    pub fn reload(&self) -> Result<(), Error> {
+        if self.inner.location == Location::StandardInput {
+            return Err(Error::ReloadStandardInput);
+        }
+
+        let value =
+            load_location_prefixed(self.inner.location.clone(), self.inner.prefix.as_deref())?;
+        self.inner.current.send_replace(Arc::new(value));
+        info!(location = %self.inner.location, "configuration reloaded");
+
+        Ok(())
+    }
+
+    /// Reloads the configuration whenever `SIGHUP` is received.
+    ///
+    /// Failed reloads are logged and leave the previous value current. A
+    /// registration failure is logged and disables reloading. The handler is
+    /// registered once per reloader and its clones; later calls have no effect.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime with signal support.
+    pub fn reload_on_hangup(&self) {
+        if self.inner.listening.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(error) => {
+                error!(%error, signal = "SIGHUP", "failed to register reload signal");
+                return;
+            }
+        };
+
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                info!(signal = "SIGHUP", "reload signal received");
+                if let Err(error) = reloader.reload() {
+                    error!(
+                        error = &error as &dyn std::error::Error,
+                        "failed to reload configuration; keeping previous configuration"
+                    );
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, process,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use nix::sys::signal::{raise, Signal};
+    use serde::{de::IgnoredAny, Deserialize, Deserializer};
+    use tokio::time::{sleep, timeout};
+
+    use super::Reloader;
+    use crate::config::Error;
+
+    /// Provides a reloadable setting.
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        /// Selects an arbitrary level.
+        level: String,
+    }
+
+    /// Publishes valid reloads and keeps the previous value after invalid ones.
+    #[test]
+    fn reloads_valid_documents_only() {
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("current time should follow the Unix epoch")
+            .as_nanos();
+        let path =
+            std::env::temp_dir().join(format!("twelve-reload-{}-{unique}.toml", process::id()));
+        fs::write(&path, "level = \"info\"\n").expect("document should be written");
+
+        let reloader = Reloader::<Config>::load(&path).expect("configuration should load");
+        let mut subscriber = reloader.subscribe();
+        assert_eq!(reloader.current().level, "info");
+
+        fs::write(&path, "level = \"debug\"\n").expect("document should be written");
+        reloader.reload().expect("valid document should reload");
+        assert!(subscriber
+            .has_changed()
+            .expect("reloader should still be alive"));
+        assert_eq!(subscriber.borrow_and_update().level, "debug");
+
+        fs::write(&path, "level = 3\n").expect("document should be written");
+        let error = reloader
+            .reload()
+            .expect_err("invalid document should be rejected");
+        fs::remove_file(&path).expect("document should be removed");
+
+        assert!(matches!(error, Error::Parse { .. }));
+        assert!(!subscriber
+            .has_changed()
+            .expect("reloader should still be alive"));
+        assert_eq!(reloader.current().level, "debug");
+    }
+
+    /// Counts how often it has been deserialized.
+    struct Counted;
+
+    /// Counts the deserialized [`Counted`] values.
+    static LOADS: AtomicUsize = AtomicUsize::new(0);
+
+    impl<'de> Deserialize<'de> for Counted {
+        /// Counts the value and ignores its contents.
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            IgnoredAny::deserialize(deserializer)?;
+            LOADS.fetch_add(1, Ordering::SeqCst);
+            Ok(Self)
+        }
+    }
+
+    /// Reloads once per signal however often reloading is requested.
+    #[tokio::test]
+    async fn reloads_once_per_hangup() {
+        let path = std::env::temp_dir().join(format!("twelve-hangup-{}.toml", process::id()));
+        fs::write(&path, "level = \"info\"\n").expect("document should be written");
+        let reloader = Reloader::<Counted>::load(&path).expect("configuration should load");
+        let mut subscriber = reloader.subscribe();
+
+        reloader.reload_on_hangup();
+        reloader.clone().reload_on_hangup();
+        raise(Signal::SIGHUP).expect("signal should be raised");
+
+        timeout(Duration::from_secs(5), subscriber.changed())
+            .await
+            .expect("signal should reload")
+            .expect("reloader should still be alive");
+        sleep(Duration::from_millis(200)).await;
+        fs::remove_file(&path).expect("document should be removed");
+
+        assert_eq!(LOADS.load(Ordering::SeqCst), 2);
+    }
+}
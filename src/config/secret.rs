@@ -0,0 +1,312 @@
+//! Keeps secret configuration values out of logs and memory dumps.
+
+use std::{
+    env,
+    fmt::{self, Debug, Display, Formatter},
+    fs,
+    marker::PhantomData,
+    path::PathBuf,
+};
+
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, SeqAccessDeserializer, StrDeserializer},
+        IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+use zeroize::Zeroize;
+
+/// Holds a secret configuration value, such as an API key or password.
+///
+/// `Debug` and `Display` print `...` instead of the value, and the value is
+/// zeroed when dropped. Use [`Secret::reveal`] at the point of use.
+///
+/// Besides the value itself, the configuration may name a file or an
+/// environment variable to read it from, which keeps the value out of the
+/// configuration document:
+///
+/// ```toml
+/// api_key = "inline value"
+/// smtp_password.file = "/run/secrets/smtp"
+/// hmac_key.env = "HMAC_KEY"
+/// ```
+///
+/// A single trailing newline is removed from file contents. Values read from
+/// a file or variable are deserialized from a string. A table is always read
+/// as the name of a source, so a secret cannot itself be a table.
+///
+/// The source is named in a table under the secret's own key, rather than in a
+/// sibling key such as `smtp_password_file`, so that every `Secret` field
+/// accepts all three forms without declaring further fields.
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    /// Wraps a secret value.
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Returns the secret value.
+    #[must_use]
+    pub fn reveal(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize + Clone> Clone for Secret<T> {
+    /// Copies the secret value.
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    /// Zeroes the secret value.
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> Debug for Secret<T> {
+    /// Formats a placeholder instead of the value.
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("...")
+    }
+}
+
+impl<T: Zeroize> Display for Secret<T> {
+    /// Formats a placeholder instead of the value.
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("...")
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    /// Wraps a secret value.
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+/// Names the source of a secret stored outside the configuration document.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+enum Indirect {
+    /// Reads the secret from a file.
+    File(PathBuf),
+
+    /// Reads the secret from an environment variable.
+    Env(String),
+}
+
+impl Indirect {
+    /// Reads the secret text from its source.
+    fn read<E: de::Error>(self) -> Result<Text<E>, E> {
+        let text = match self {
+            Self::File(path) => {
+                let mut contents = fs::read_to_string(&path).map_err(|error| {
+                    E::custom(format!(
+                        "failed to read secret from {}: {error}",
+                        path.display()
+                    ))
+                })?;
+                let trimmed = contents
+                    .strip_suffix('\n')
+                    .map(|contents| contents.strip_suffix('\r').unwrap_or(contents))
+                    .unwrap_or(&contents)
+                    .len();
+                contents.truncate(trimmed);
+                contents
+            }
+            Self::Env(name) => env::var(&name).map_err(|_| {
+                E::custom(format!(
+                    "failed to read secret from environment variable {name}"
+                ))
+            })?,
+        };
+
+        Ok(Text(text, PhantomData))
+    }
+}
+
+/// Deserializes a value from secret text, zeroing the text when dropped.
+struct Text<E>(String, PhantomData<E>);
+
+impl<E> Drop for Text<E> {
+    /// Zeroes the text.
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<'de, E: de::Error> Deserializer<'de> for Text<E> {
+    type Error = E;
+
+    /// Presents the text as a borrowed string.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, E>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_str(&self.0)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Deserializes a secret from its value or from a table naming its source.
+struct SecretVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for SecretVisitor<T>
+where
+    T: Deserialize<'de> + Zeroize,
+{
+    type Value = Secret<T>;
+
+    /// Describes the accepted forms.
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a secret value or a table with a `file` or `env` key")
+    }
+
+    /// Deserializes the value from a boolean.
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+        T::deserialize(value.into_deserializer()).map(Secret)
+    }
+
+    /// Deserializes the value from a signed integer.
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        T::deserialize(value.into_deserializer()).map(Secret)
+    }
+
+    /// Deserializes the value from an unsigned integer.
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        T::deserialize(value.into_deserializer()).map(Secret)
+    }
+
+    /// Deserializes the value from a float.
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        T::deserialize(value.into_deserializer()).map(Secret)
+    }
+
+    /// Deserializes the value from a string.
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        T::deserialize(StrDeserializer::new(value)).map(Secret)
+    }
+
+    /// Deserializes the value from an owned string, zeroing it afterwards.
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Self::Value, E> {
+        T::deserialize(Text(value, PhantomData)).map(Secret)
+    }
+
+    /// Deserializes the value from a sequence.
+    fn visit_seq<A>(self, sequence: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        T::deserialize(SeqAccessDeserializer::new(sequence)).map(Secret)
+    }
+
+    /// Reads the value from the source named by a `file` or `env` key.
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let text = Indirect::deserialize(MapAccessDeserializer::new(map))?.read()?;
+        T::deserialize(text).map(Secret)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Secret<T>
+where
+    T: Deserialize<'de> + Zeroize,
+{
+    /// Deserializes the value or reads it from the named source.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(SecretVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use serde::Deserialize;
+
+    use super::Secret;
+
+    /// Provides secrets from each supported source.
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        /// Provides an inline secret.
+        inline: Secret<String>,
+
+        /// Provides a secret read from a file.
+        file: Secret<String>,
+
+        /// Provides a secret read from an environment variable.
+        env: Secret<String>,
+    }
+
+    /// Reads secrets inline, from files and from variables, and redacts them.
+    #[test]
+    fn reads_and_redacts_secrets() {
+        let path = env::temp_dir().join(format!("twelve-secret-{}", process::id()));
+        fs::write(&path, "from file\n").expect("secret file should be writable");
+        env::set_var("TWELVE_TEST_SECRET", "from env");
+
+        let config: Config = toml::from_str(&format!(
+            "inline = 'inline'\nfile.file = '{}'\nenv.env = 'TWELVE_TEST_SECRET'\n",
+            path.display()
+        ))
+        .expect("secrets should deserialize");
+        fs::remove_file(&path).expect("secret file should be removable");
+
+        assert_eq!(config.inline.reveal(), "inline");
+        assert_eq!(config.file.reveal(), "from file");
+        assert_eq!(config.env.reveal(), "from env");
+        assert_eq!(
+            format!("{config:?}"),
+            "Config { inline: ..., file: ..., env: ... }"
+        );
+        assert_eq!(config.inline.to_string(), "...");
+
+        let missing = toml::from_str::<Config>(
+            "inline = 'a'\nfile.file = '/nonexistent/secret'\nenv = 'c'\n",
+        )
+        .expect_err("missing file should fail");
+        assert!(missing.to_string().contains("/nonexistent/secret"));
+    }
+
+    /// Reports the inner error when an inline value has the wrong type.
+    #[test]
+    fn reports_inner_errors() {
+        /// Provides a numeric secret.
+        #[derive(Debug, Deserialize)]
+        struct Port {
+            /// Holds the secret.
+            key: Secret<u16>,
+        }
+
+        let error = toml::from_str::<Port>("key = 'abc'\n")
+            .expect_err("string should not deserialize as u16");
+        let message = error.to_string();
+        assert!(message.contains("expected u16"), "{message}");
+        assert!(!message.contains("untagged"), "{message}");
+
+        let port: Port = toml::from_str("key = 8080\n").expect("integer should deserialize");
+        assert_eq!(*port.key.reveal(), 8080);
+
+        let error = toml::from_str::<Port>("key.path = '/run/secret'\n")
+            .expect_err("unknown source should fail");
+        let message = error.to_string();
+        assert!(message.contains("unknown variant `path`"), "{message}");
+    }
+}
@@ -0,0 +1,327 @@
+//! Serves liveness and readiness endpoints for load balancers and orchestrators.
+//!
+//! [`Health`] builds a router with three endpoints:
+//!
+//! * `GET /livez` (and its alias `GET /healthz`) answers `200 OK` while the
+//!   process is able to serve requests at all.
+//! * `GET /readyz` runs the registered readiness checks concurrently and
+//!   answers `200 OK` if all of them pass, or `503 Service Unavailable`
+//!   otherwise. The JSON body reports the status and latency of each check.
+//!
+//! Readiness flips to `503 Service Unavailable` as soon as the [`Shutdown`]
+//! handle is triggered, without running any checks, so that load balancers stop
+//! routing traffic to the instance while it drains. Set a
+//! [readiness grace period](crate::shutdown::Drain::readiness_grace) so that
+//! the server keeps accepting probes long enough for them to observe this.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use axum::Router;
+//! use twelve::{health::Health, shutdown::Shutdown};
+//!
+//! let shutdown = Shutdown::new();
+//! let health = Health::new(shutdown.clone())
+//!     .timeout(Duration::from_secs(2))
+//!     .check("cache", || async { Ok::<_, std::io::Error>(()) });
+//! let app: Router = Router::new().merge(health.router());
+//! ```
+
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::shutdown::Shutdown;
+
+/// Bounds the time a readiness check may take unless configured otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs a readiness check, returning a description of its failure.
+type CheckFn =
+    dyn Fn() -> Pin<Box<dyn Future<Output = Result<(), String>> + Send>> + Send + Sync + 'static;
+
+/// Configures health endpoints and readiness checks.
+///
+/// See the [module documentation](self) for the served endpoints.
+#[derive(Clone, Debug)]
+#[must_use]
+pub struct Health {
+    /// Flips readiness once triggered.
+    shutdown: Shutdown,
+
+    /// Bounds the time each check may take.
+    timeout: Duration,
+
+    /// Holds the readiness checks in registration order.
+    checks: Vec<Check>,
+}
+
+/// Names a readiness check.
+#[derive(Clone)]
+struct Check {
+    /// Identifies the check in reports and logs.
+    name: String,
+
+    /// Runs the check.
+    run: Arc<CheckFn>,
+}
+
+impl Debug for Check {
+    /// Formats the check name.
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Check")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// Reports readiness as returned by `GET /readyz`.
+#[derive(Debug, Serialize)]
+struct Readiness {
+    /// Summarizes the outcome: `ready`, `unavailable` or `shutting down`.
+    status: &'static str,
+
+    /// Reports each check in registration order.
+    checks: Vec<CheckReport>,
+}
+
+/// Reports the outcome of a single readiness check.
+#[derive(Debug, Serialize)]
+struct CheckReport {
+    /// Identifies the check.
+    name: String,
+
+    /// Reports `ok` or `error`.
+    status: &'static str,
+
+    /// Reports the time the check took in milliseconds.
+    latency_ms: f64,
+
+    /// Describes the failure, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Health {
+    /// Constructs health endpoints without readiness checks.
+    ///
+    /// Readiness fails once `shutdown` is triggered.
+    pub fn new(shutdown: Shutdown) -> Self {
+        Self {
+            shutdown,
+            timeout: DEFAULT_TIMEOUT,
+            checks: Vec::new(),
+        }
+    }
+
+    /// Sets the time after which a readiness check counts as failed.
+    ///
+    /// Defaults to five seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Registers a named readiness check.
+    ///
+    /// `check` is called for every readiness request. It fails the request if
+    /// it returns an error, panics, or exceeds the timeout.
+    pub fn check<F, Fut, E>(mut self, name: impl Into<String>, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display,
+    {
+        self.checks.push(Check {
+            name: name.into(),
+            run: Arc::new(move || {
+                let check = check();
+                Box::pin(async move { check.await.map_err(|error| error.to_string()) })
+            }),
+        });
+        self
+    }
+
+    /// Builds a router serving `/livez`, `/healthz` and `/readyz`.
+    pub fn router<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let health = Arc::new(self);
+
+        Router::new()
+            .route("/livez", get(live))
+            .route("/healthz", get(live))
+            .route(
+                "/readyz",
+                get(move || {
+                    let health = health.clone();
+                    async move { health.ready().await }
+                }),
+            )
+    }
+
+    /// Runs all checks concurrently and reports readiness.
+    async fn ready(&self) -> (StatusCode, Json<Readiness>) {
+        if self.shutdown.is_triggered() {
+            let readiness = Readiness {
+                status: "shutting down",
+                checks: Vec::new(),
+            };
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(readiness));
+        }
+
+        let running: Vec<_> = self
+            .checks
+            .iter()
+            .map(|check| {
+                let started = Instant::now();
+                let task = tokio::spawn(tokio::time::timeout(self.timeout, (check.run)()));
+                (check.name.clone(), started, task)
+            })
+            .collect();
+
+        let mut checks = Vec::with_capacity(running.len());
+        for (name, started, task) in running {
+            let result = match task.await {
+                Ok(Ok(result)) => result,
+                Ok(Err(_)) => Err(format!("timed out after {:?}", self.timeout)),
+                Err(_) => Err("check panicked".to_owned()),
+            };
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+            if let Err(error) = &result {
+                warn!(check = name, %error, "readiness check failed");
+            }
+
+            checks.push(CheckReport {
+                name,
+                status: if result.is_ok() { "ok" } else { "error" },
+                latency_ms,
+                error: result.err(),
+            });
+        }
+
+        if checks.iter().all(|check| check.error.is_none()) {
+            let readiness = Readiness {
+                status: "ready",
+                checks,
+            };
+            (StatusCode::OK, Json(readiness))
+        } else {
+            let readiness = Readiness {
+                status: "unavailable",
+                checks,
+            };
+            (StatusCode::SERVICE_UNAVAILABLE, Json(readiness))
+        }
+    }
+}
+
+/// Reports that the process is alive.
+async fn live() -> &'static str {
+    "ok"
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use super::Health;
+    use crate::shutdown::Shutdown;
+
+    /// Requests `uri` and returns the status and body.
+    async fn get(router: &Router, uri: &str) -> (StatusCode, Vec<u8>) {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .body(Body::empty())
+                    .expect("request should be valid"),
+            )
+            .await
+            .expect("router should be infallible");
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("body should be readable")
+            .to_bytes();
+
+        (status, body.to_vec())
+    }
+
+    /// Parses a readiness report.
+    fn parse(body: &[u8]) -> Value {
+        serde_json::from_slice(body).expect("readiness should be JSON")
+    }
+
+    /// Reports every check and fails readiness if any check fails.
+    #[tokio::test]
+    async fn reports_checks() {
+        let passing: Router = Health::new(Shutdown::new())
+            .check("database", || async { Ok::<_, io::Error>(()) })
+            .router();
+        let (status, body) = get(&passing, "/readyz").await;
+        let report = parse(&body);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["status"], "ready");
+        assert_eq!(report["checks"][0]["name"], "database");
+        assert_eq!(report["checks"][0]["status"], "ok");
+        assert!(report["checks"][0]["latency_ms"].is_f64());
+
+        let failing: Router = Health::new(Shutdown::new())
+            .timeout(Duration::from_millis(10))
+            .check("database", || async { Ok::<_, io::Error>(()) })
+            .check("cache", || async { Err("connection refused") })
+            .check("queue", || async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok::<_, io::Error>(())
+            })
+            .router();
+        let (status, body) = get(&failing, "/readyz").await;
+        let report = parse(&body);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(report["status"], "unavailable");
+        assert_eq!(report["checks"][0]["status"], "ok");
+        assert_eq!(report["checks"][1]["error"], "connection refused");
+        assert_eq!(report["checks"][2]["error"], "timed out after 10ms");
+    }
+
+    /// Fails readiness but not liveness once shutdown is triggered.
+    #[tokio::test]
+    async fn flips_readiness_on_shutdown() {
+        let shutdown = Shutdown::new();
+        let router: Router = Health::new(shutdown.clone()).router();
+        assert_eq!(get(&router, "/readyz").await.0, StatusCode::OK);
+
+        shutdown.trigger();
+        let (status, body) = get(&router, "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(parse(&body)["status"], "shutting down");
+        assert_eq!(
+            get(&router, "/livez").await,
+            (StatusCode::OK, b"ok".to_vec())
+        );
+        assert_eq!(get(&router, "/healthz").await.0, StatusCode::OK);
+    }
+}
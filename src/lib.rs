@@ -2,10 +2,17 @@
 #![doc = include_str!("../README.md")]
 
 pub mod config;
+pub mod health;
 pub mod listener;
 pub mod logging;
 pub mod mount;
 #[cfg(feature = "html")]
 #[cfg_attr(docsrs, doc(cfg(feature = "html")))]
 pub mod page;
+#[cfg(feature = "postgres")]
+#[cfg_attr(docsrs, doc(cfg(feature = "postgres")))]
+pub mod postgres;
+pub mod proxy;
+pub mod request;
+pub mod route;
 pub mod shutdown;
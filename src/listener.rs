@@ -5,11 +5,22 @@
 //! assigned port when binding TCP port zero.
 //!
 //! Numeric IP socket addresses select TCP; absolute filesystem paths select
-//! Unix-domain sockets.
+//! Unix-domain sockets. `systemd:` and `fd:` addresses adopt an inherited
+//! socket instead, detecting whether it is a TCP or Unix-domain listener.
 //!
 //! [`ListenAddress`] implements [`serde::Deserialize`] and can be used directly
 //! in application configuration.
 //!
+//! [`Listener::bind_with`] applies [`UnixSocket`] options to Unix-domain
+//! sockets: replacing stale socket files and setting their mode and ownership.
+//! Socket files created by a listener are removed when it is dropped.
+//!
+//! [`Listeners`] binds every address of a
+//! [`crate::config::ListenAddresses`] list and accepts from whichever socket
+//! is ready, reporting the accepting socket alongside the peer as an
+//! [`Endpoint`]. Both listener types provide [`Address`] as
+//! [`axum::extract::ConnectInfo`].
+//!
 //! ```no_run
 //! use twelve::{config::ListenAddress, listener::Listener};
 //!
@@ -28,19 +39,33 @@ use std::{
     fmt::{self, Display, Formatter},
     io,
     net::SocketAddr,
+    os::fd::RawFd,
     path::Path,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use axum::serve::Listener as AxumListener;
+use axum::{
+    extract::{connect_info::Connected, ConnectInfo},
+    http::Extensions,
+    serve::{IncomingStream, Listener as AxumListener},
+};
 use thiserror::Error;
 use tokio::{
     io::{AsyncRead, AsyncWrite, ReadBuf},
     net::{TcpListener, TcpStream, UnixListener, UnixStream},
 };
 
-use crate::config::ListenAddress;
+pub use self::multi::{Endpoint, Listeners};
+use self::{
+    activation::{Activation, Claim},
+    unix::SocketFile,
+};
+use crate::config::{ListenAddress, UnixSocket};
+
+mod activation;
+mod multi;
+mod unix;
 
 /// Provides a TCP or Unix-domain listener.
 ///
@@ -52,14 +77,28 @@ pub struct Listener {
 
     /// Holds the effective local address.
     local_address: Address,
+
+    /// Releases resources tied to the listener once it has been closed.
+    ///
+    /// Declared after `inner` so that it is released last.
+    _cleanup: Cleanup,
 }
 
 impl Listener {
-    /// Binds the configured listener.
+    /// Binds the configured listener with default Unix socket options.
     pub async fn bind(address: &ListenAddress) -> Result<Self, Error> {
+        Self::bind_with(address, &UnixSocket::default()).await
+    }
+
+    /// Binds the configured listener, applying `options` to a Unix socket.
+    pub async fn bind_with(address: &ListenAddress, options: &UnixSocket) -> Result<Self, Error> {
         match address {
             ListenAddress::Tcp(address) => Self::bind_tcp(*address).await,
-            ListenAddress::Unix(path) => Self::bind_unix(path),
+            ListenAddress::Unix(path) => Self::bind_unix(path, options),
+            ListenAddress::Systemd(name) => {
+                Self::adopt(Activation::from_env()?.select(name.as_deref())?)
+            }
+            ListenAddress::Fd(fd) => Self::adopt(*fd),
         }
     }
 
@@ -87,24 +126,52 @@ impl Listener {
         Ok(Self {
             inner: Inner::Tcp(listener),
             local_address: Address::Tcp(local_address),
+            _cleanup: Cleanup::None,
         })
     }
 
     /// Binds a Unix-domain listener.
-    fn bind_unix(path: &Path) -> Result<Self, Error> {
-        let listener = UnixListener::bind(path).map_err(|source| Error::BindUnix {
-            path: path.to_path_buf(),
-            source,
-        })?;
-        let local_address = listener
-            .local_addr()
+    fn bind_unix(path: &Path, options: &UnixSocket) -> Result<Self, Error> {
+        let (listener, socket_file) = unix::bind(path, options)?;
+        // The socket may have been bound under a staging path and linked into
+        // place, so its configured path is reported instead of `local_addr`.
+        let local_address = std::os::unix::net::SocketAddr::from_pathname(path)
             .map_err(|source| Error::ReadUnixAddress { source })?;
 
         Ok(Self {
             inner: Inner::Unix(listener),
-            local_address: Address::Unix(local_address),
+            local_address: Address::Unix(local_address.into()),
+            _cleanup: Cleanup::Remove {
+                _socket_file: socket_file,
+            },
         })
     }
+
+    /// Adopts an inherited TCP or Unix-domain listener.
+    fn adopt(fd: RawFd) -> Result<Self, Error> {
+        let (inner, local_address, claim) = activation::adopt(fd)?;
+
+        Ok(Self {
+            inner,
+            local_address,
+            _cleanup: Cleanup::Release { _claim: claim },
+        })
+    }
+
+    /// Polls for a connection without waiting.
+    pub(super) fn poll_accept(
+        &self,
+        context: &mut Context<'_>,
+    ) -> Poll<io::Result<(Connection, Address)>> {
+        match &self.inner {
+            Inner::Tcp(listener) => listener
+                .poll_accept(context)
+                .map_ok(|(connection, peer)| (Connection::Tcp(connection), Address::Tcp(peer))),
+            Inner::Unix(listener) => listener
+                .poll_accept(context)
+                .map_ok(|(connection, peer)| (Connection::Unix(connection), Address::Unix(peer))),
+        }
+    }
 }
 
 impl AxumListener for Listener {
@@ -133,6 +200,13 @@ impl AxumListener for Listener {
     }
 }
 
+impl Connected<IncomingStream<'_, Listener>> for Address {
+    /// Reports the peer of a connection.
+    fn connect_info(stream: IncomingStream<'_, Listener>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
 /// Holds a transport-specific listener.
 enum Inner {
     /// Holds a TCP listener.
@@ -142,6 +216,27 @@ enum Inner {
     Unix(UnixListener),
 }
 
+/// Releases resources tied to a listener.
+///
+/// Variants are only held for their `Drop` implementations.
+#[derive(Debug)]
+enum Cleanup {
+    /// Requires no cleanup.
+    None,
+
+    /// Removes the bound socket file.
+    Remove {
+        /// Removes the file when dropped.
+        _socket_file: SocketFile,
+    },
+
+    /// Releases an adopted descriptor.
+    Release {
+        /// Releases the descriptor when dropped.
+        _claim: Claim,
+    },
+}
+
 /// Identifies a TCP or Unix-domain socket endpoint.
 #[derive(Clone, Debug)]
 pub enum Address {
@@ -170,6 +265,21 @@ impl Address {
             Self::Unix(address) => address.as_pathname(),
         }
     }
+
+    /// Returns the peer recorded as [`ConnectInfo`] in request extensions.
+    ///
+    /// Accepts an [`Address`], an [`Endpoint`], or a plain [`SocketAddr`].
+    pub(crate) fn from_connect_info(extensions: &Extensions) -> Option<Self> {
+        if let Some(ConnectInfo(address)) = extensions.get::<ConnectInfo<Self>>() {
+            Some(address.clone())
+        } else if let Some(ConnectInfo(endpoint)) = extensions.get::<ConnectInfo<Endpoint>>() {
+            Some(endpoint.peer.clone())
+        } else {
+            extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(address)| Self::Tcp(*address))
+        }
+    }
 }
 
 impl Display for Address {
@@ -294,6 +404,78 @@ pub enum Error {
         source: io::Error,
     },
 
+    /// Indicates that an existing socket path could not be probed.
+    #[error("failed to probe existing Unix socket at {path}")]
+    ProbeUnix {
+        /// Provides the configured path.
+        path: std::path::PathBuf,
+
+        /// Provides the filesystem or socket error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// Indicates that the socket path exists but is not a socket.
+    #[error("refusing to replace {path}, which is not a Unix socket")]
+    NotUnixSocket {
+        /// Provides the configured path.
+        path: std::path::PathBuf,
+    },
+
+    /// Indicates that another listener still accepts on the socket path.
+    #[error("Unix socket at {path} is in use by a running listener")]
+    UnixInUse {
+        /// Provides the configured path.
+        path: std::path::PathBuf,
+    },
+
+    /// Indicates that a stale socket file could not be removed.
+    #[error("failed to remove stale Unix socket at {path}")]
+    RemoveStaleUnix {
+        /// Provides the configured path.
+        path: std::path::PathBuf,
+
+        /// Provides the filesystem error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// Indicates that the socket file mode could not be set.
+    #[error("failed to set mode of Unix socket at {path}")]
+    SetUnixMode {
+        /// Provides the configured path.
+        path: std::path::PathBuf,
+
+        /// Provides the filesystem error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// Indicates that the configured socket owner does not exist.
+    #[error("unknown user {name}")]
+    UnknownUser {
+        /// Provides the configured user.
+        name: String,
+    },
+
+    /// Indicates that the configured socket group does not exist.
+    #[error("unknown group {name}")]
+    UnknownGroup {
+        /// Provides the configured group.
+        name: String,
+    },
+
+    /// Indicates that the socket file ownership could not be set.
+    #[error("failed to set owner of Unix socket at {path}")]
+    SetUnixOwner {
+        /// Provides the configured path.
+        path: std::path::PathBuf,
+
+        /// Provides the filesystem error.
+        #[source]
+        source: io::Error,
+    },
+
     /// Indicates that a TCP listener address could not be read.
     #[error("failed to read TCP listener address")]
     ReadTcpAddress {
@@ -309,6 +491,54 @@ pub enum Error {
         #[source]
         source: io::Error,
     },
+
+    /// Indicates that several listeners were requested from an empty list.
+    #[error("at least one listener is required")]
+    NoListeners,
+
+    /// Indicates that systemd did not pass sockets to this process.
+    #[error("no sockets were passed by systemd to this process")]
+    Activation,
+
+    /// Indicates that systemd passed several sockets and none was named.
+    #[error("systemd passed {count} sockets; select one by name")]
+    AmbiguousActivation {
+        /// Provides the number of passed sockets.
+        count: usize,
+    },
+
+    /// Indicates that systemd did not pass a socket with the requested name.
+    #[error("systemd did not pass a socket named {name}")]
+    MissingActivation {
+        /// Provides the requested name.
+        name: String,
+    },
+
+    /// Indicates that an inherited descriptor was already adopted.
+    #[error("inherited socket {fd} has already been adopted")]
+    AlreadyAdopted {
+        /// Provides the descriptor.
+        fd: RawFd,
+    },
+
+    /// Indicates that an inherited descriptor could not be adopted.
+    #[error("failed to adopt inherited socket {fd}")]
+    Adopt {
+        /// Provides the descriptor.
+        fd: RawFd,
+
+        /// Provides the socket error.
+        #[source]
+        source: io::Error,
+    },
+
+    /// Indicates that an inherited descriptor is not a listening TCP or
+    /// Unix-domain stream socket.
+    #[error("inherited descriptor {fd} is not a listening TCP or Unix-domain stream socket")]
+    UnsupportedSocket {
+        /// Provides the descriptor.
+        fd: RawFd,
+    },
 }
 
 #[cfg(test)]
@@ -316,13 +546,20 @@ mod tests {
     use std::{
         fs,
         net::{IpAddr, Ipv4Addr, SocketAddr},
+        os::{
+            fd::IntoRawFd,
+            unix::{
+                fs::MetadataExt,
+                net::{UnixListener, UnixStream},
+            },
+        },
         path::PathBuf,
         process,
         time::{SystemTime, UNIX_EPOCH},
     };
 
-    use super::Listener;
-    use crate::config::ListenAddress;
+    use super::{Error, Listener};
+    use crate::config::{ListenAddress, UnixSocket};
 
     /// Verifies that ephemeral TCP ports are reported after binding.
     #[tokio::test]
@@ -339,12 +576,7 @@ mod tests {
     /// Verifies the effective address of a Unix-domain listener.
     #[tokio::test]
     async fn reports_unix_socket_path() {
-        let unique = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("current time should follow the Unix epoch")
-            .as_nanos();
-        let path =
-            std::env::temp_dir().join(format!("twelve-listener-{}-{unique}.sock", process::id()));
+        let path = socket_path("listener");
         let socket = SocketFile(path.clone());
         let listener = Listener::bind(&ListenAddress::Unix(path.clone()))
             .await
@@ -357,6 +589,108 @@ mod tests {
         drop(socket);
     }
 
+    /// Verifies that stale socket files are replaced only when requested.
+    #[tokio::test]
+    async fn replaces_stale_unix_socket() {
+        let path = socket_path("stale");
+        let socket = SocketFile(path.clone());
+        drop(UnixListener::bind(&path).expect("Unix socket should bind"));
+        let address = ListenAddress::Unix(path.clone());
+
+        assert!(matches!(
+            Listener::bind(&address).await,
+            Err(Error::BindUnix { .. })
+        ));
+        let options = UnixSocket {
+            remove_stale: true,
+            ..UnixSocket::default()
+        };
+        let listener = Listener::bind_with(&address, &options)
+            .await
+            .expect("stale Unix socket should be replaced");
+
+        assert_eq!(listener.local_address().as_pathname(), Some(path.as_path()));
+
+        drop(listener);
+        assert!(!path.exists());
+        drop(socket);
+    }
+
+    /// Verifies that a socket with a live listener is never replaced.
+    #[tokio::test]
+    async fn refuses_live_unix_socket() {
+        let path = socket_path("live");
+        let socket = SocketFile(path.clone());
+        let live = UnixListener::bind(&path).expect("Unix socket should bind");
+        let options = UnixSocket {
+            remove_stale: true,
+            ..UnixSocket::default()
+        };
+
+        assert!(matches!(
+            Listener::bind_with(&ListenAddress::Unix(path.clone()), &options).await,
+            Err(Error::UnixInUse { .. })
+        ));
+        assert!(path.exists());
+
+        drop(live);
+        drop(socket);
+    }
+
+    /// Verifies that the socket file mode and ownership are applied.
+    #[tokio::test]
+    async fn applies_unix_socket_permissions() {
+        let path = socket_path("mode");
+        let socket = SocketFile(path.clone());
+        let metadata = fs::metadata(std::env::temp_dir()).expect("temp dir should exist");
+        let options = UnixSocket {
+            mode: Some("0600".parse().expect("mode should parse")),
+            owner: Some(metadata.uid().to_string()),
+            group: Some(metadata.gid().to_string()),
+            ..UnixSocket::default()
+        };
+        let listener = Listener::bind_with(&ListenAddress::Unix(path.clone()), &options)
+            .await
+            .expect("Unix listener should bind");
+
+        let metadata = fs::metadata(&path).expect("socket file should exist");
+        assert_eq!(metadata.mode() & 0o7777, 0o600);
+        assert_eq!(metadata.nlink(), 1);
+        assert_eq!(listener.local_address().as_pathname(), Some(path.as_path()));
+        UnixStream::connect(&path).expect("socket should accept connections");
+
+        drop(listener);
+        drop(socket);
+    }
+
+    /// Verifies that an inherited Unix-domain socket is adopted by descriptor.
+    #[tokio::test]
+    async fn adopts_inherited_unix_socket() {
+        let path = socket_path("adopt");
+        let socket = SocketFile(path.clone());
+        let fd = UnixListener::bind(&path)
+            .expect("Unix socket should bind")
+            .into_raw_fd();
+        let listener = Listener::bind(&ListenAddress::Fd(fd))
+            .await
+            .expect("inherited Unix socket should be adopted");
+
+        assert_eq!(listener.port(), None);
+        assert_eq!(listener.local_address().as_pathname(), Some(path.as_path()));
+
+        drop(listener);
+        drop(socket);
+    }
+
+    /// Returns a unique socket path in the temporary directory.
+    fn socket_path(name: &str) -> PathBuf {
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("current time should follow the Unix epoch")
+            .as_nanos();
+        std::env::temp_dir().join(format!("twelve-{name}-{}-{unique}.sock", process::id()))
+    }
+
     /// Removes a test socket from the filesystem.
     struct SocketFile(PathBuf);
 
@@ -0,0 +1,324 @@
+//! Adopts listening sockets inherited from a service manager.
+//!
+//! systemd passes sockets starting at descriptor 3 and describes them through
+//! `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES`.
+
+use std::{
+    collections::BTreeSet,
+    env,
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixListener as StdUnixListener,
+    },
+    process,
+    sync::Mutex,
+};
+
+use nix::{
+    errno::Errno,
+    sys::socket::{
+        getsockname, getsockopt, sockopt, AddressFamily, SockType, SockaddrLike, SockaddrStorage,
+    },
+};
+use tokio::net::{TcpListener, UnixListener};
+
+use super::{Address, Error, Inner};
+
+/// Identifies the first descriptor passed by systemd.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Tracks descriptors that have already been adopted by this process.
+static ADOPTED: Mutex<BTreeSet<RawFd>> = Mutex::new(BTreeSet::new());
+
+/// Describes the sockets passed to this process by systemd.
+#[derive(Debug)]
+pub(super) struct Activation {
+    /// Identifies the first passed descriptor.
+    first: RawFd,
+
+    /// Counts the passed descriptors.
+    count: usize,
+
+    /// Names the passed descriptors in order.
+    names: Vec<String>,
+}
+
+impl Activation {
+    /// Reads the activation environment of the current process.
+    pub(super) fn from_env() -> Result<Self, Error> {
+        Self::from_lookup(|key| env::var(key).ok(), process::id(), LISTEN_FDS_START)
+    }
+
+    /// Reads an activation environment through `lookup`.
+    ///
+    /// The environment only applies when `LISTEN_PID` names `pid`, so sockets
+    /// intended for a parent process are never adopted.
+    fn from_lookup<F>(lookup: F, pid: u32, first: RawFd) -> Result<Self, Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if lookup("LISTEN_PID").and_then(|value| value.parse().ok()) != Some(pid) {
+            return Err(Error::Activation);
+        }
+        let count = lookup("LISTEN_FDS")
+            .and_then(|value| value.parse().ok())
+            .filter(|count| *count > 0)
+            .ok_or(Error::Activation)?;
+        let names = lookup("LISTEN_FDNAMES")
+            .map(|value| value.split(':').map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        Ok(Self {
+            first,
+            count,
+            names,
+        })
+    }
+
+    /// Selects the sole passed descriptor or the one named `name`.
+    pub(super) fn select(&self, name: Option<&str>) -> Result<RawFd, Error> {
+        let index = match name {
+            None if self.count == 1 => 0,
+            None => return Err(Error::AmbiguousActivation { count: self.count }),
+            Some(name) => self
+                .names
+                .iter()
+                .take(self.count)
+                .position(|candidate| candidate == name)
+                .ok_or_else(|| Error::MissingActivation {
+                    name: name.to_owned(),
+                })?,
+        };
+
+        RawFd::try_from(index)
+            .ok()
+            .and_then(|index| self.first.checked_add(index))
+            .ok_or(Error::AmbiguousActivation { count: self.count })
+    }
+}
+
+/// Records an adopted descriptor until the owning listener is dropped.
+///
+/// The descriptor stays open while claimed, so its number cannot be reused by
+/// another socket and adopted a second time.
+#[derive(Debug)]
+pub(super) struct Claim(RawFd);
+
+impl Claim {
+    /// Records a descriptor as adopted, rejecting repeated adoption.
+    fn new(fd: RawFd) -> Result<Self, Error> {
+        if ADOPTED
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .insert(fd)
+        {
+            Ok(Self(fd))
+        } else {
+            Err(Error::AlreadyAdopted { fd })
+        }
+    }
+}
+
+impl Drop for Claim {
+    /// Releases the descriptor number once its listener has been closed.
+    fn drop(&mut self) {
+        ADOPTED
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .remove(&self.0);
+    }
+}
+
+/// Takes ownership of an inherited listening socket.
+///
+/// The descriptor is probed before ownership is taken: anything other than a
+/// listening TCP or Unix-domain stream socket is rejected and left open. Each
+/// descriptor can be adopted once while its listener is alive.
+pub(super) fn adopt(fd: RawFd) -> Result<(Inner, Address, Claim), Error> {
+    let claim = Claim::new(fd)?;
+
+    // SAFETY: the descriptor is only borrowed while probing it; a closed
+    // descriptor makes each probe fail with `EBADF`.
+    let probe = unsafe { BorrowedFd::borrow_raw(fd) };
+    let tcp = match probe_listener(probe) {
+        Ok(Some(tcp)) => tcp,
+        Ok(None) | Err(Errno::ENOTSOCK) => return Err(Error::UnsupportedSocket { fd }),
+        Err(errno) => {
+            return Err(Error::Adopt {
+                fd,
+                source: errno.into(),
+            })
+        }
+    };
+    // SAFETY: the probe proved the descriptor to be an open listening socket,
+    // `claim` guarantees that it is owned at most once, and this process takes
+    // ownership of inherited sockets when adopting them.
+    let owned = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    if tcp {
+        let listener = std::net::TcpListener::from(owned);
+        listener
+            .set_nonblocking(true)
+            .map_err(|source| Error::Adopt { fd, source })?;
+        let listener =
+            TcpListener::from_std(listener).map_err(|source| Error::Adopt { fd, source })?;
+        let address = listener
+            .local_addr()
+            .map_err(|source| Error::ReadTcpAddress { source })?;
+        return Ok((Inner::Tcp(listener), Address::Tcp(address), claim));
+    }
+
+    let listener = StdUnixListener::from(owned);
+    listener
+        .set_nonblocking(true)
+        .map_err(|source| Error::Adopt { fd, source })?;
+    let listener =
+        UnixListener::from_std(listener).map_err(|source| Error::Adopt { fd, source })?;
+    let address = listener
+        .local_addr()
+        .map_err(|source| Error::ReadUnixAddress { source })?;
+
+    Ok((Inner::Unix(listener), Address::Unix(address), claim))
+}
+
+/// Reports whether `fd` is a listening TCP socket, a listening Unix-domain
+/// stream socket, or neither.
+fn probe_listener(fd: BorrowedFd<'_>) -> Result<Option<bool>, Errno> {
+    if getsockopt(&fd, sockopt::SockType)? != SockType::Stream
+        || !getsockopt(&fd, sockopt::AcceptConn)?
+    {
+        return Ok(None);
+    }
+
+    Ok(
+        match getsockname::<SockaddrStorage>(fd.as_raw_fd())?.family() {
+            Some(AddressFamily::Inet | AddressFamily::Inet6) => Some(true),
+            Some(AddressFamily::Unix) => Some(false),
+            _ => None,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, TcpListener, UdpSocket},
+        os::fd::{AsRawFd, BorrowedFd, IntoRawFd, RawFd},
+    };
+
+    use nix::{
+        fcntl::{fcntl, FcntlArg},
+        sys::socket::{bind, socket, AddressFamily, SockFlag, SockType, SockaddrIn},
+        unistd::pipe,
+    };
+
+    use super::{adopt, Activation};
+    use crate::listener::{Address, Error};
+
+    /// Constructs an activation environment from literal variables.
+    fn activation(variables: &[(&str, &str)], first: RawFd) -> Result<Activation, Error> {
+        let variables: HashMap<_, _> = variables
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Activation::from_lookup(|key| variables.get(key).cloned(), 42, first)
+    }
+
+    /// Ignores sockets that were passed to another process.
+    #[test]
+    fn ignores_foreign_activation() {
+        assert!(matches!(
+            activation(&[("LISTEN_PID", "7"), ("LISTEN_FDS", "1")], 3),
+            Err(Error::Activation)
+        ));
+        assert!(matches!(
+            activation(&[("LISTEN_FDS", "1")], 3),
+            Err(Error::Activation)
+        ));
+    }
+
+    /// Selects passed descriptors by position and name.
+    #[test]
+    fn selects_descriptors() {
+        let activation = activation(
+            &[
+                ("LISTEN_PID", "42"),
+                ("LISTEN_FDS", "2"),
+                ("LISTEN_FDNAMES", "http:metrics"),
+            ],
+            3,
+        )
+        .expect("activation should apply");
+
+        assert_eq!(activation.select(Some("http")).ok(), Some(3));
+        assert_eq!(activation.select(Some("metrics")).ok(), Some(4));
+        assert!(matches!(
+            activation.select(Some("admin")),
+            Err(Error::MissingActivation { .. })
+        ));
+        assert!(matches!(
+            activation.select(None),
+            Err(Error::AmbiguousActivation { count: 2 })
+        ));
+    }
+
+    /// Adopts a pre-bound TCP socket passed on a known descriptor.
+    #[tokio::test]
+    async fn adopts_passed_tcp_socket() {
+        let socket = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("TCP socket should bind");
+        let port = socket
+            .local_addr()
+            .expect("TCP socket should have an address")
+            .port();
+        let fd = socket.into_raw_fd();
+        let activation = activation(
+            &[
+                ("LISTEN_PID", "42"),
+                ("LISTEN_FDS", "1"),
+                ("LISTEN_FDNAMES", "http"),
+            ],
+            fd,
+        )
+        .expect("activation should apply");
+
+        let selected = activation
+            .select(Some("http"))
+            .expect("socket should be selected");
+        let (_listener, address, _claim) = adopt(selected).expect("TCP socket should be adopted");
+
+        assert!(matches!(address, Address::Tcp(_)));
+        assert_eq!(address.port(), Some(port));
+        assert!(matches!(adopt(fd), Err(Error::AlreadyAdopted { .. })));
+    }
+
+    /// Rejects descriptors that are not listening stream sockets and leaves
+    /// them open.
+    #[test]
+    fn rejects_other_descriptors() {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).expect("UDP socket should bind");
+        let unlistened = socket(
+            AddressFamily::Inet,
+            SockType::Stream,
+            SockFlag::empty(),
+            None,
+        )
+        .expect("TCP socket should be created");
+        bind(unlistened.as_raw_fd(), &SockaddrIn::new(127, 0, 0, 1, 0))
+            .expect("TCP socket should bind");
+        let (reader, _writer) = pipe().expect("pipe should be created");
+
+        for fd in [udp.as_raw_fd(), unlistened.as_raw_fd(), reader.as_raw_fd()] {
+            assert!(matches!(
+                adopt(fd),
+                Err(Error::UnsupportedSocket { fd: rejected }) if rejected == fd
+            ));
+            // SAFETY: the descriptor is owned by a value that is still alive.
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            assert!(
+                fcntl(fd, FcntlArg::F_GETFD).is_ok(),
+                "{fd:?} should stay open"
+            );
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Accepts connections from several listeners through one Axum listener.
+
+use std::{
+    fmt::{self, Display, Formatter},
+    future::poll_fn,
+    io,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener as AxumListener},
+};
+use tokio::time::sleep;
+use tracing::error;
+
+use super::{Address, Connection, Error, Listener};
+use crate::config::{ListenAddresses, UnixSocket};
+
+/// Delays accepting again after an error that is not tied to a connection.
+const ACCEPT_RETRY: Duration = Duration::from_secs(1);
+
+/// Provides one listener over several bound sockets.
+///
+/// Accepting polls every socket, starting after the one that accepted last so
+/// that a busy socket cannot starve the others, and hands Axum a connection
+/// from whichever socket is ready. Connections are only accepted while Axum
+/// asks for one, so none are accepted and then dropped once graceful shutdown
+/// stops accepting.
+pub struct Listeners {
+    /// Holds the bound listeners in configuration order.
+    listeners: Vec<Listener>,
+
+    /// Holds the effective local addresses in configuration order.
+    local_addresses: Vec<Address>,
+
+    /// Identifies the listener polled first by the next accept.
+    next: usize,
+}
+
+impl Listeners {
+    /// Binds every configured listener with default Unix socket options.
+    pub async fn bind(addresses: &ListenAddresses) -> Result<Self, Error> {
+        Self::bind_with(addresses, &UnixSocket::default()).await
+    }
+
+    /// Binds every configured listener, applying `options` to Unix sockets.
+    ///
+    /// Sockets bound before a failure are closed again.
+    pub async fn bind_with(
+        addresses: &ListenAddresses,
+        options: &UnixSocket,
+    ) -> Result<Self, Error> {
+        let mut listeners = Vec::new();
+        for address in addresses {
+            listeners.push(Listener::bind_with(address, options).await?);
+        }
+
+        Self::new(listeners)
+    }
+
+    /// Accepts connections from already bound listeners.
+    ///
+    /// Fails if `listeners` is empty, as accepting would never complete.
+    pub fn new(listeners: Vec<Listener>) -> Result<Self, Error> {
+        if listeners.is_empty() {
+            return Err(Error::NoListeners);
+        }
+
+        let local_addresses = listeners
+            .iter()
+            .map(|listener| listener.local_address().clone())
+            .collect();
+
+        Ok(Self {
+            listeners,
+            local_addresses,
+            next: 0,
+        })
+    }
+
+    /// Returns the effective local addresses in configuration order.
+    #[must_use]
+    pub fn local_addresses(&self) -> &[Address] {
+        &self.local_addresses
+    }
+
+    /// Polls each listener in turn for a connection.
+    fn poll_accept(
+        &mut self,
+        context: &mut Context<'_>,
+    ) -> Poll<io::Result<(Connection, Endpoint)>> {
+        let count = self.listeners.len();
+        for offset in 0..count {
+            let index = (self.next + offset) % count;
+            let listener = &self.listeners[index];
+            if let Poll::Ready(accepted) = listener.poll_accept(context) {
+                self.next = (index + 1) % count;
+                return Poll::Ready(accepted.map(|(connection, peer)| {
+                    let endpoint = Endpoint {
+                        local: listener.local_address().clone(),
+                        peer,
+                    };
+                    (connection, endpoint)
+                }));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl AxumListener for Listeners {
+    type Addr = Endpoint;
+    type Io = Connection;
+
+    /// Accepts a connection from whichever listener is ready first.
+    ///
+    /// Like Axum's own listeners, errors caused by a single connection are
+    /// skipped, and other errors, such as running out of file descriptors,
+    /// are logged before retrying after a second.
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            match poll_fn(|context| self.poll_accept(context)).await {
+                Ok(accepted) => return accepted,
+                Err(error) if is_connection_error(&error) => {}
+                Err(error) => {
+                    error!(%error, "failed to accept connection");
+                    sleep(ACCEPT_RETRY).await;
+                }
+            }
+        }
+    }
+
+    /// Fails, as several listeners have no single local address.
+    ///
+    /// Use [`Listeners::local_addresses`] instead.
+    #[inline]
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "several listeners have no single local address",
+        ))
+    }
+}
+
+/// Reports whether an accept error only affected a single connection.
+fn is_connection_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Identifies an accepted connection's peer and the listener that accepted it.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    /// Identifies the listener that accepted the connection.
+    pub local: Address,
+
+    /// Identifies the connecting peer.
+    pub peer: Address,
+}
+
+impl Display for Endpoint {
+    /// Formats the peer and the accepting listener.
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{} via {}", self.peer, self.local)
+    }
+}
+
+impl Connected<IncomingStream<'_, Listeners>> for Endpoint {
+    /// Reports the peer and accepting listener of a connection.
+    fn connect_info(stream: IncomingStream<'_, Listeners>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
+impl Connected<IncomingStream<'_, Listeners>> for Address {
+    /// Reports the peer of a connection.
+    fn connect_info(stream: IncomingStream<'_, Listeners>) -> Self {
+        stream.remote_addr().peer.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use axum::serve::Listener as _;
+    use serde::Deserialize;
+    use tokio::{io::AsyncWriteExt, net::TcpStream};
+    use toml::Value;
+
+    use super::Listeners;
+    use crate::{config::ListenAddresses, listener::Error};
+
+    /// Accepts connections from each bound listener and reports its endpoint.
+    #[tokio::test]
+    async fn accepts_from_every_listener() {
+        let addresses = ListenAddresses::deserialize(Value::Array(vec![
+            Value::from("127.0.0.1:0"),
+            Value::from("127.0.0.1:0"),
+        ]))
+        .expect("addresses should deserialize");
+        let mut listeners = Listeners::bind(&addresses)
+            .await
+            .expect("listeners should bind");
+        let ports: Vec<_> = listeners
+            .local_addresses()
+            .iter()
+            .map(|address| address.port().expect("listener should use TCP"))
+            .collect();
+
+        for port in ports {
+            let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+                .await
+                .expect("client should connect");
+            client.shutdown().await.expect("client should shut down");
+            let (_, endpoint) = listeners.accept().await;
+
+            assert_eq!(endpoint.local.port(), Some(port));
+            assert!(endpoint.peer.port().is_some());
+        }
+        assert!(listeners.local_addr().is_err());
+    }
+
+    /// Refuses to accept from an empty list of listeners.
+    #[test]
+    fn requires_listeners() {
+        assert!(matches!(
+            Listeners::new(Vec::new()),
+            Err(Error::NoListeners)
+        ));
+    }
+}
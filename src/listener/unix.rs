@@ -0,0 +1,254 @@
+//! Creates Unix-domain socket files and removes them again.
+
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    io::{self, ErrorKind},
+    os::unix::{
+        fs::{chown, DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt},
+        net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use nix::unistd::{Group, User};
+use tokio::net::UnixListener;
+use tracing::{info, warn};
+
+use super::Error;
+use crate::config::UnixSocket;
+
+/// Removes a bound socket file when the listener is dropped.
+///
+/// The file is only removed while it is still the socket that was bound, so a
+/// file replaced by another process is left alone.
+#[derive(Debug)]
+pub(super) struct SocketFile {
+    /// Identifies the socket path.
+    path: PathBuf,
+
+    /// Identifies the device holding the socket file.
+    device: u64,
+
+    /// Identifies the socket file's inode.
+    inode: u64,
+}
+
+impl Drop for SocketFile {
+    /// Removes the socket file if it has not been replaced.
+    fn drop(&mut self) {
+        match fs::symlink_metadata(&self.path) {
+            Ok(metadata) if metadata.dev() == self.device && metadata.ino() == self.inode => {}
+            _ => return,
+        }
+        if let Err(error) = fs::remove_file(&self.path) {
+            if error.kind() != ErrorKind::NotFound {
+                warn!(%error, path = %self.path.display(), "failed to remove Unix socket");
+            }
+        }
+    }
+}
+
+/// Binds a Unix-domain listener and applies the socket file options.
+///
+/// When a mode, owner or group is configured, the socket is bound inside a
+/// private directory and linked into place only once its permissions have been
+/// applied, so it is never reachable with the default permissions.
+pub(super) fn bind(path: &Path, options: &UnixSocket) -> Result<(UnixListener, SocketFile), Error> {
+    if options.remove_stale {
+        remove_stale(path)?;
+    }
+
+    let bind_error = |source| Error::BindUnix {
+        path: path.to_path_buf(),
+        source,
+    };
+    if options.mode.is_none() && options.owner.is_none() && options.group.is_none() {
+        let listener = UnixListener::bind(path).map_err(bind_error)?;
+        return Ok((listener, SocketFile::new(path).map_err(bind_error)?));
+    }
+
+    let staging = Staging::new(path).map_err(bind_error)?;
+    let listener = UnixListener::bind(&staging.socket).map_err(bind_error)?;
+    apply_permissions(&staging.socket, path, options)?;
+    fs::hard_link(&staging.socket, path).map_err(bind_error)?;
+    drop(staging);
+
+    Ok((listener, SocketFile::new(path).map_err(bind_error)?))
+}
+
+/// Applies the configured mode and ownership to the socket file at `socket`,
+/// reporting failures for the configured `path`.
+fn apply_permissions(socket: &Path, path: &Path, options: &UnixSocket) -> Result<(), Error> {
+    if let Some(mode) = options.mode {
+        fs::set_permissions(socket, Permissions::from_mode(mode.into())).map_err(|source| {
+            Error::SetUnixMode {
+                path: path.to_path_buf(),
+                source,
+            }
+        })?;
+    }
+    if options.owner.is_some() || options.group.is_some() {
+        let owner = options
+            .owner
+            .as_deref()
+            .map(|name| {
+                resolve_user(name).ok_or_else(|| Error::UnknownUser {
+                    name: name.to_owned(),
+                })
+            })
+            .transpose()?;
+        let group = options
+            .group
+            .as_deref()
+            .map(|name| {
+                resolve_group(name).ok_or_else(|| Error::UnknownGroup {
+                    name: name.to_owned(),
+                })
+            })
+            .transpose()?;
+        chown(socket, owner, group).map_err(|source| Error::SetUnixOwner {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    }
+
+    Ok(())
+}
+
+impl SocketFile {
+    /// Records the socket file bound at `path`.
+    fn new(path: &Path) -> io::Result<Self> {
+        let metadata = fs::symlink_metadata(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+}
+
+/// Holds a private directory in which a socket is bound before it is linked
+/// into place, removing both when dropped.
+struct Staging {
+    /// Identifies the private directory.
+    directory: PathBuf,
+
+    /// Identifies the socket path inside the directory.
+    socket: PathBuf,
+}
+
+impl Staging {
+    /// Creates a private directory next to `path`.
+    fn new(path: &Path) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let directory = path.with_file_name(format!(
+            ".twelve-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        DirBuilder::new().mode(0o700).create(&directory)?;
+
+        Ok(Self {
+            socket: directory.join("socket"),
+            directory,
+        })
+    }
+}
+
+impl Drop for Staging {
+    /// Removes the staging socket and directory.
+    fn drop(&mut self) {
+        let removed = fs::remove_file(&self.socket)
+            .or_else(ignore_not_found)
+            .and_then(|()| fs::remove_dir(&self.directory));
+        if let Err(error) = removed {
+            warn!(%error, path = %self.directory.display(), "failed to remove staging directory");
+        }
+    }
+}
+
+/// Removes an existing socket file unless a listener still accepts on it.
+fn remove_stale(path: &Path) -> Result<(), Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {}
+        Ok(_) => {
+            return Err(Error::NotUnixSocket {
+                path: path.to_path_buf(),
+            })
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(source) => {
+            return Err(Error::ProbeUnix {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    }
+
+    match UnixStream::connect(path) {
+        Ok(_) => Err(Error::UnixInUse {
+            path: path.to_path_buf(),
+        }),
+        Err(error) if error.kind() == ErrorKind::ConnectionRefused => {
+            info!(path = %path.display(), "removing stale Unix socket");
+            fs::remove_file(path)
+                .or_else(ignore_not_found)
+                .map_err(|source| Error::RemoveStaleUnix {
+                    path: path.to_path_buf(),
+                    source,
+                })
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(source) => Err(Error::ProbeUnix {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Treats a file that vanished concurrently as removed.
+fn ignore_not_found(error: io::Error) -> io::Result<()> {
+    if error.kind() == ErrorKind::NotFound {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// Resolves a user name or numeric ID through the system user database.
+fn resolve_user(name: &str) -> Option<u32> {
+    name.parse().ok().or_else(|| {
+        User::from_name(name)
+            .ok()
+            .flatten()
+            .map(|user| user.uid.as_raw())
+    })
+}
+
+/// Resolves a group name or numeric ID through the system group database.
+fn resolve_group(name: &str) -> Option<u32> {
+    name.parse().ok().or_else(|| {
+        Group::from_name(name)
+            .ok()
+            .flatten()
+            .map(|group| group.gid.as_raw())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_group, resolve_user};
+
+    /// Resolves names through the system databases and accepts numeric IDs.
+    #[test]
+    fn resolves_users_and_groups() {
+        assert_eq!(resolve_user("root"), Some(0));
+        assert_eq!(resolve_group("root"), Some(0));
+        assert_eq!(resolve_user("33"), Some(33));
+        assert_eq!(resolve_group("twelve-nonexistent-group"), None);
+    }
+}
@@ -1,13 +1,33 @@
 //! Initializes process-wide application logging.
 //!
 //! [`init()`] installs a human-readable [`tracing`] subscriber configured by a
-//! validated [`LogFilter`]. Events emitted through the
+//! validated [`LogFilter`], or a journal subscriber when running under
+//! systemd's journal. Events emitted through the
 //! [`log`](https://docs.rs/log) facade are forwarded to the same subscriber.
 //!
+//! [`init_with()`] additionally selects a [`LogFormat`], usually taken from
+//! [`Core::log_format`](crate::config::Core::log_format). The structured
+//! formats use a fixed schema:
+//!
+//! * `json` writes one object per line with `timestamp` (RFC 3339), `level`,
+//!   `target`, `message`, the remaining event fields, and `spans`, a list of
+//!   the spans in scope from the root, each with its `name` and fields.
+//! * `logfmt` writes `ts`, `level`, `target` and `msg`, then the remaining
+//!   event fields, then `span`, the colon-separated span names from the root,
+//!   followed by the fields of those spans.
+//! * `journald` sends entries to the journal's native socket, mapping levels to
+//!   syslog priorities and fields to upper-cased journal fields prefixed with
+//!   `F_`.
+//!
+//! The default, `auto`, selects `journald` when systemd connected standard
+//! output to the journal, as indicated by `JOURNAL_STREAM`, and `full`
+//! otherwise. If the journal socket cannot be reached, logs fall back to
+//! `full` on standard output.
+//!
 //! This is the opinionated default for applications that do not need custom
-//! subscriber layers or output formats. Applications requiring structured
-//! output or additional layers can instead convert [`LogFilter`] into a
-//! [`tracing_subscriber::EnvFilter`] and construct their own subscriber.
+//! subscriber layers. Applications requiring additional layers can instead
+//! convert [`LogFilter`] into a [`tracing_subscriber::EnvFilter`] and construct
+//! their own subscriber.
 //!
 //! ```no_run
 //! use twelve::{config::LogFilter, logging};
@@ -18,18 +38,312 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! The returned [`Handle`] replaces the active filter at runtime.
+//! [`Handle::router`] exposes it over HTTP: `GET` returns the current filter
+//! and `PUT` validates and installs the filter sent as the request body. The
+//! router performs no authorization and must be mounted behind the
+//! application's admin authentication.
+//!
+//! ```no_run
+//! use axum::Router;
+//! use twelve::{config::LogFilter, logging};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let handle = logging::init(LogFilter::default())?;
+//! let admin: Router = Router::new().nest("/admin/log-filter", handle.router());
+//! # let _ = admin;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
 
-use tracing_subscriber::util::{SubscriberInitExt, TryInitError};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use tracing::{info, warn, Subscriber};
+use tracing_subscriber::{
+    fmt::{self, MakeWriter},
+    layer::SubscriberExt,
+    registry,
+    registry::LookupSpan,
+    reload::{self, Layer},
+    util::{SubscriberInitExt, TryInitError},
+    EnvFilter, Layer as _, Registry,
+};
 
-use crate::config::LogFilter;
+use self::{
+    journald::Journald,
+    logfmt::{Logfmt, LogfmtFields},
+};
+use crate::config::{LogFilter, LogFormat};
+
+mod journald;
+mod logfmt;
 
 /// Installs a global tracing subscriber using the validated filter.
 ///
-/// Returns an error if a global tracing subscriber or compatible log adapter
-/// has already been installed.
-pub fn init(filter: LogFilter) -> Result<(), TryInitError> {
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .finish()
-        .try_init()
+/// Returns a handle for replacing the filter, or an error if a global tracing
+/// subscriber or compatible log adapter has already been installed.
+pub fn init(filter: LogFilter) -> Result<Handle, TryInitError> {
+    init_with(filter, LogFormat::default())
+}
+
+/// Installs a global tracing subscriber writing logs in `format`.
+///
+/// Returns a handle for replacing the filter, or an error if a global tracing
+/// subscriber or compatible log adapter has already been installed.
+///
+/// If the journal cannot be used, logs are written to standard output in the
+/// default format instead and a warning is logged.
+pub fn init_with(filter: LogFilter, format: LogFormat) -> Result<Handle, TryInitError> {
+    let (filter, handle) = Layer::new(EnvFilter::from(filter));
+    let (output, fallback) = match output(format, io::stdout) {
+        Ok(output) => (output, None),
+        Err(error) => (fmt::layer().boxed(), Some(error)),
+    };
+    registry().with(filter).with(output).try_init()?;
+    if let Some(error) = fallback {
+        warn!(%error, "failed to connect to the journal; logging to standard output");
+    }
+
+    Ok(Handle { filter: handle })
+}
+
+/// Builds the output layer for `format`, writing text formats to `writer`.
+fn output<S, W>(
+    format: LogFormat,
+    writer: W,
+) -> io::Result<Box<dyn tracing_subscriber::Layer<S> + Send + Sync>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let format = match format {
+        LogFormat::Auto if journald::stdout_is_journal() => LogFormat::Journald,
+        LogFormat::Auto => LogFormat::Full,
+        format => format,
+    };
+    let layer = fmt::layer().with_writer(writer);
+    Ok(match format {
+        LogFormat::Auto | LogFormat::Full => layer.boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+        LogFormat::Logfmt => layer.event_format(Logfmt).fmt_fields(LogfmtFields).boxed(),
+        LogFormat::Journald => Journald::new(journald::SOCKET)?.boxed(),
+    })
+}
+
+/// Replaces the filter of the subscriber installed by [`init()`].
+#[derive(Clone, Debug)]
+pub struct Handle {
+    /// Reloads the subscriber's filter layer.
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl Handle {
+    /// Returns the active filter.
+    ///
+    /// Fails only if the subscriber has been dropped.
+    pub fn filter(&self) -> Result<LogFilter, reload::Error> {
+        self.filter
+            .with_current(|filter| LogFilter::from(filter.clone()))
+    }
+
+    /// Installs a new filter, affecting all subsequent events.
+    ///
+    /// Fails only if the subscriber has been dropped.
+    pub fn set_filter(&self, filter: LogFilter) -> Result<(), reload::Error> {
+        let description = filter.to_string();
+        self.filter.reload(EnvFilter::from(filter))?;
+        info!(filter = description, "log filter replaced");
+
+        Ok(())
+    }
+
+    /// Builds a router that reads and replaces the active filter.
+    ///
+    /// `GET /` returns the filter as plain text and `PUT /` installs the filter
+    /// in the request body, responding with `400 Bad Request` if it is
+    /// invalid. The router performs no authorization.
+    pub fn router<S>(&self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route("/", get(show_filter).put(replace_filter))
+            .with_state(self.clone())
+    }
+}
+
+/// Responds with the active filter.
+async fn show_filter(State(handle): State<Handle>) -> Response {
+    match handle.filter() {
+        Ok(filter) => filter.to_string().into_response(),
+        Err(error) => unavailable(&error),
+    }
+}
+
+/// Validates and installs the filter sent as the request body.
+async fn replace_filter(State(handle): State<Handle>, body: String) -> Response {
+    let filter: LogFilter = match body.trim().parse() {
+        Ok(filter) => filter,
+        Err(error) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("invalid log filter: {error}"),
+            )
+                .into_response()
+        }
+    };
+
+    match handle.set_filter(filter.clone()) {
+        Ok(()) => filter.to_string().into_response(),
+        Err(error) => unavailable(&error),
+    }
+}
+
+/// Reports that the subscriber can no longer be reconfigured.
+fn unavailable(error: &reload::Error) -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("log filter unavailable: {error}"),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use tracing::{info, info_span};
+    use tracing_subscriber::{layer::SubscriberExt, registry, reload::Layer, EnvFilter};
+
+    use super::{output, Handle};
+    use crate::config::{LogFilter, LogFormat};
+
+    /// Collects formatted output in memory.
+    #[derive(Clone, Default)]
+    pub(super) struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        /// Returns the collected output.
+        pub(super) fn contents(&self) -> String {
+            String::from_utf8(
+                self.0
+                    .lock()
+                    .expect("buffer should not be poisoned")
+                    .clone(),
+            )
+            .expect("output should be UTF-8")
+        }
+    }
+
+    impl io::Write for Buffer {
+        /// Appends formatted output.
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0
+                .lock()
+                .expect("buffer should not be poisoned")
+                .extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        /// Does nothing, as the buffer is in memory.
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes JSON lines with the documented top-level schema.
+    #[test]
+    fn formats_json_with_stable_schema() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = registry()
+            .with(output(LogFormat::Json, move || writer.clone()).expect("layer should be built"));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = info_span!("request", method = "GET").entered();
+            info!(status = 200, "request done");
+        });
+
+        let line: Value =
+            serde_json::from_str(&buffer.contents()).expect("output should be a JSON line");
+        assert!(line["timestamp"].is_string());
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "twelve::logging::tests");
+        assert_eq!(line["message"], "request done");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["spans"][0]["name"], "request");
+        assert_eq!(line["spans"][0]["method"], "GET");
+    }
+
+    /// Sends a request to `router` and returns the status and body text.
+    async fn send(router: &Router, method: Method, body: &'static str) -> (StatusCode, String) {
+        let request = Request::builder()
+            .method(method)
+            .uri("/")
+            .body(Body::from(body))
+            .expect("request should be valid");
+        let response = router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("router should be infallible");
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("body should be readable")
+            .to_bytes();
+
+        (
+            status,
+            String::from_utf8(body.to_vec()).expect("body should be UTF-8"),
+        )
+    }
+
+    /// Shows, validates, and replaces the active filter over HTTP.
+    #[tokio::test]
+    async fn replaces_filter_over_http() {
+        let (filter, handle) = Layer::new(EnvFilter::from(LogFilter::default()));
+        let _subscriber = registry().with(filter);
+        let router = Handle { filter: handle }.router();
+
+        let (status, body) = send(&router, Method::GET, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, LogFilter::default().to_string());
+
+        let (status, _) = send(&router, Method::PUT, "myapp=[").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(&router, Method::PUT, "debug\n").await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = send(&router, Method::GET, "").await;
+        assert_eq!(body, "debug");
+    }
 }
@@ -0,0 +1,434 @@
+//! Sends events to the systemd journal over its native protocol.
+
+use std::{
+    env,
+    fmt::Debug,
+    fs::File,
+    io::{self, Write},
+    os::{
+        fd::{AsFd, AsRawFd},
+        unix::{fs::MetadataExt, net::UnixDatagram},
+    },
+    path::Path,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, SealFlag},
+    sys::{
+        memfd::{memfd_create, MFdFlags},
+        socket::{sendmsg, ControlMessage, MsgFlags},
+    },
+};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Level, Subscriber,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// Identifies the journal's native protocol socket.
+pub(super) const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Writes each event as one native journal entry.
+///
+/// The entry carries `MESSAGE`, `PRIORITY`, `SYSLOG_IDENTIFIER`, `TARGET`,
+/// `CODE_FILE` and `CODE_LINE`, the remaining event fields, `SPAN` with the
+/// colon-separated span names from the root, and the fields of those spans.
+/// Field names are upper-cased, characters outside `[A-Z0-9_]` are replaced
+/// by underscores, and every name but `MESSAGE` is prefixed with `F_`, so that
+/// fields cannot override the trusted ones above. Entries too large for a datagram are passed to the journal
+/// in a sealed memory file, as its native protocol provides. Entries that
+/// cannot be sent are dropped.
+#[derive(Debug)]
+pub(super) struct Journald {
+    /// Sends datagrams to the journal.
+    socket: UnixDatagram,
+
+    /// Names the program in every entry.
+    identifier: String,
+}
+
+/// Holds the encoded fields of a span.
+#[derive(Debug, Default)]
+struct SpanFields(Vec<u8>);
+
+impl Journald {
+    /// Constructs a layer sending entries to the socket at `path`.
+    ///
+    /// Fails if the journal socket does not exist or refuses connections.
+    pub(super) fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let identifier = env::current_exe()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "unknown".to_owned());
+
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(path)?;
+
+        Ok(Self { socket, identifier })
+    }
+
+    /// Sends an entry, passing it in a sealed memory file if it is too large
+    /// for a datagram.
+    fn send(&self, entry: &[u8]) -> io::Result<()> {
+        match self.socket.send(entry) {
+            Err(error) if error.raw_os_error() == Some(Errno::EMSGSIZE as i32) => {
+                self.send_memfd(entry)
+            }
+            result => result.map(drop),
+        }
+    }
+
+    /// Sends an entry as a sealed memory file.
+    fn send_memfd(&self, entry: &[u8]) -> io::Result<()> {
+        let memfd = memfd_create(
+            c"twelve-journal-entry",
+            MFdFlags::MFD_ALLOW_SEALING | MFdFlags::MFD_CLOEXEC,
+        )?;
+        let mut file = File::from(memfd);
+        file.write_all(entry)?;
+        fcntl(
+            &file,
+            FcntlArg::F_ADD_SEALS(
+                SealFlag::F_SEAL_SHRINK
+                    | SealFlag::F_SEAL_GROW
+                    | SealFlag::F_SEAL_WRITE
+                    | SealFlag::F_SEAL_SEAL,
+            ),
+        )?;
+        sendmsg::<()>(
+            self.socket.as_raw_fd(),
+            &[],
+            &[ControlMessage::ScmRights(&[file.as_raw_fd()])],
+            MsgFlags::empty(),
+            None,
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<S> Layer<S> for Journald
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    /// Encodes the fields of a new span.
+    fn on_new_span(&self, attributes: &Attributes<'_>, id: &Id, context: Context<'_, S>) {
+        let Some(span) = context.span(id) else {
+            return;
+        };
+        let mut fields = SpanFields::default();
+        attributes.record(&mut Visitor(&mut fields.0));
+        span.extensions_mut().insert(fields);
+    }
+
+    /// Encodes fields recorded after a span was created.
+    fn on_record(&self, id: &Id, values: &Record<'_>, context: Context<'_, S>) {
+        let Some(span) = context.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) = extensions.get_mut::<SpanFields>() {
+            values.record(&mut Visitor(&mut fields.0));
+        }
+    }
+
+    /// Sends an event and its span context as one entry.
+    fn on_event(&self, event: &Event<'_>, context: Context<'_, S>) {
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+
+        let mut entry = Vec::new();
+        put(&mut entry, "PRIORITY", priority(*metadata.level()));
+        put(&mut entry, "SYSLOG_IDENTIFIER", &self.identifier);
+        put(&mut entry, "TARGET", metadata.target());
+        if let Some(file) = metadata.file() {
+            put(&mut entry, "CODE_FILE", file);
+        }
+        if let Some(line) = metadata.line() {
+            put(&mut entry, "CODE_LINE", &line.to_string());
+        }
+        event.record(&mut Visitor(&mut entry));
+
+        if let Some(scope) = context.event_scope(event) {
+            let mut path = String::new();
+            for span in scope.from_root() {
+                if !path.is_empty() {
+                    path.push(':');
+                }
+                path.push_str(span.name());
+                if let Some(fields) = span.extensions().get::<SpanFields>() {
+                    entry.extend_from_slice(&fields.0);
+                }
+            }
+            put(&mut entry, "SPAN", &path);
+        }
+
+        let _ = self.send(&entry);
+    }
+}
+
+/// Encodes visited fields as journal fields.
+struct Visitor<'a>(&'a mut Vec<u8>);
+
+impl Visit for Visitor<'_> {
+    /// Records a string field without debug quoting.
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if let Some(name) = field_name(field) {
+            put(self.0, &name, value);
+        }
+    }
+
+    /// Records any other field using its debug representation.
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if let Some(name) = field_name(field) {
+            put(self.0, &name, &format!("{value:?}"));
+        }
+    }
+}
+
+/// Converts a tracing field name into a prefixed journal field name.
+///
+/// Returns `None` for the `log.` fields already reflected in the metadata.
+fn field_name(field: &Field) -> Option<String> {
+    let name = match field.name() {
+        "message" => return Some("MESSAGE".to_owned()),
+        name if name.starts_with("log.") => return None,
+        name => name.trim_start_matches("r#"),
+    };
+    let mut sanitized = String::from("F_");
+    sanitized.extend(name.chars().map(|character| match character {
+        'a'..='z' => character.to_ascii_uppercase(),
+        'A'..='Z' | '0'..='9' | '_' => character,
+        _ => '_',
+    }));
+    sanitized.truncate(64);
+
+    Some(sanitized)
+}
+
+/// Appends a field, using the length-prefixed form for multi-line values.
+fn put(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+/// Maps a tracing level to a syslog priority.
+fn priority(level: Level) -> &'static str {
+    match level {
+        Level::ERROR => "3",
+        Level::WARN => "4",
+        Level::INFO => "6",
+        Level::DEBUG | Level::TRACE => "7",
+    }
+}
+
+/// Reports whether standard output is connected to the journal.
+///
+/// systemd sets `JOURNAL_STREAM` to the device and inode of the stream it
+/// connected, so a redirected standard output does not match.
+pub(super) fn stdout_is_journal() -> bool {
+    let Some(stream) = env::var_os("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Ok(stdout) = io::stdout().as_fd().try_clone_to_owned() else {
+        return false;
+    };
+    let Ok(metadata) = File::from(stdout).metadata() else {
+        return false;
+    };
+
+    stream.to_str() == Some(&format!("{}:{}", metadata.dev(), metadata.ino()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        env,
+        fs::File,
+        io::{IoSliceMut, Read, Seek},
+        os::{
+            fd::{AsRawFd, FromRawFd, RawFd},
+            unix::net::UnixDatagram,
+        },
+        path::PathBuf,
+        process,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use nix::{
+        cmsg_space,
+        sys::socket::{recvmsg, ControlMessageOwned, MsgFlags},
+    };
+    use tracing::{info_span, warn};
+    use tracing_subscriber::{layer::SubscriberExt, registry};
+
+    use super::Journald;
+
+    /// Decodes a native journal entry into its fields.
+    fn decode(mut entry: &[u8]) -> BTreeMap<String, Vec<String>> {
+        let mut fields = BTreeMap::<String, Vec<String>>::new();
+        while !entry.is_empty() {
+            let end = entry
+                .iter()
+                .position(|&byte| byte == b'=' || byte == b'\n')
+                .expect("field name should be terminated");
+            let name = String::from_utf8(entry[..end].to_vec()).expect("name should be UTF-8");
+            let value;
+            if entry[end] == b'=' {
+                let length = entry[end + 1..]
+                    .iter()
+                    .position(|&byte| byte == b'\n')
+                    .expect("value should be terminated");
+                value = &entry[end + 1..end + 1 + length];
+                entry = &entry[end + 2 + length..];
+            } else {
+                let mut length = [0; 8];
+                length.copy_from_slice(&entry[end + 1..end + 9]);
+                let length = usize::try_from(u64::from_le_bytes(length))
+                    .expect("length should fit in memory");
+                value = &entry[end + 9..end + 9 + length];
+                assert_eq!(entry[end + 9 + length], b'\n');
+                entry = &entry[end + 10 + length..];
+            }
+            fields
+                .entry(name)
+                .or_default()
+                .push(String::from_utf8(value.to_vec()).expect("value should be UTF-8"));
+        }
+
+        fields
+    }
+
+    /// Sends events with mapped priority, span fields, and multi-line values.
+    #[test]
+    fn sends_native_entries() {
+        let path = socket_path("native");
+        let journal = UnixDatagram::bind(&path).expect("journal socket should bind");
+        let subscriber = registry().with(Journald::new(&path).expect("layer should be created"));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = info_span!("request", request_id = "abc").entered();
+            warn!(
+                user.name = "alice",
+                detail = "line one\nline two",
+                "slow request"
+            );
+        });
+
+        let mut buffer = vec![0; 4096];
+        let length = journal.recv(&mut buffer).expect("entry should be received");
+        std::fs::remove_file(&path).expect("journal socket should be removed");
+        let fields = decode(&buffer[..length]);
+
+        assert_eq!(fields["MESSAGE"], ["slow request"]);
+        assert_eq!(fields["PRIORITY"], ["4"]);
+        assert_eq!(fields["TARGET"], ["twelve::logging::journald::tests"]);
+        assert_eq!(fields["F_USER_NAME"], ["alice"]);
+        assert_eq!(fields["F_DETAIL"], ["line one\nline two"]);
+        assert_eq!(fields["F_REQUEST_ID"], ["abc"]);
+        assert_eq!(fields["SPAN"], ["request"]);
+        assert!(fields.contains_key("CODE_LINE"));
+    }
+
+    /// Prefixes event and span fields so they cannot override trusted fields.
+    #[test]
+    fn prefixes_fields() {
+        let path = socket_path("prefixed");
+        let journal = UnixDatagram::bind(&path).expect("journal socket should bind");
+        let subscriber = registry().with(Journald::new(&path).expect("layer should be created"));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = info_span!("request", syslog_identifier = "other").entered();
+            warn!(priority = 0, target = "other", _pid = 1, "spoofed");
+        });
+
+        let mut buffer = vec![0; 4096];
+        let length = journal.recv(&mut buffer).expect("entry should be received");
+        std::fs::remove_file(&path).expect("journal socket should be removed");
+        let fields = decode(&buffer[..length]);
+
+        assert_eq!(fields["PRIORITY"], ["4"]);
+        assert_eq!(fields["F_PRIORITY"], ["0"]);
+        assert_eq!(fields["TARGET"], ["twelve::logging::journald::tests"]);
+        assert_eq!(fields["F_TARGET"], ["other"]);
+        assert_eq!(fields["SYSLOG_IDENTIFIER"].len(), 1);
+        assert_eq!(fields["F_SYSLOG_IDENTIFIER"], ["other"]);
+        assert_eq!(fields["F__PID"], ["1"]);
+        assert!(!fields.contains_key("_PID"));
+    }
+
+    /// Refuses to construct a layer without a journal socket.
+    #[test]
+    fn requires_journal_socket() {
+        assert!(Journald::new(socket_path("missing")).is_err());
+    }
+
+    /// Passes entries too large for a datagram in a sealed memory file.
+    #[test]
+    fn sends_large_entries_as_memfd() {
+        let path = socket_path("large");
+        let journal = UnixDatagram::bind(&path).expect("journal socket should bind");
+        let subscriber = registry().with(Journald::new(&path).expect("layer should be created"));
+        let detail = "x".repeat(1 << 20);
+
+        tracing::subscriber::with_default(subscriber, || {
+            warn!(detail, "large entry");
+        });
+
+        let mut buffer = [0; 16];
+        let mut data = [IoSliceMut::new(&mut buffer)];
+        let mut control = cmsg_space!([RawFd; 1]);
+        let message = recvmsg::<()>(
+            journal.as_raw_fd(),
+            &mut data,
+            Some(&mut control),
+            MsgFlags::empty(),
+        )
+        .expect("entry should be received");
+        let Some(ControlMessageOwned::ScmRights(fds)) = message
+            .cmsgs()
+            .expect("control messages should decode")
+            .next()
+        else {
+            panic!("entry should carry a descriptor");
+        };
+        std::fs::remove_file(&path).expect("journal socket should be removed");
+
+        // SAFETY: the descriptor was just received and is owned by nothing else.
+        let mut file = unsafe { File::from_raw_fd(fds[0]) };
+        let mut entry = Vec::new();
+        file.rewind().expect("memory file should be seekable");
+        file.read_to_end(&mut entry)
+            .expect("memory file should be readable");
+        let fields = decode(&entry);
+
+        assert_eq!(fields["MESSAGE"], ["large entry"]);
+        assert_eq!(fields["F_DETAIL"], [detail]);
+    }
+
+    /// Returns a unique socket path in the temporary directory.
+    fn socket_path(name: &str) -> PathBuf {
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("current time should follow the Unix epoch")
+            .as_nanos();
+        env::temp_dir().join(format!(
+            "twelve-journal-{name}-{}-{unique}.sock",
+            process::id()
+        ))
+    }
+}
@@ -0,0 +1,206 @@
+//! Formats events as [logfmt](https://brandur.org/logfmt) lines.
+
+use std::fmt::{self, Debug, Write as _};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{
+        format::Writer,
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormatFields, FormattedFields,
+    },
+    registry::LookupSpan,
+};
+
+/// Writes one `key=value` line per event.
+///
+/// Every line starts with `ts`, `level` and `target`, followed by `msg`, the
+/// remaining event fields, the `span` path from the root span, and finally
+/// the fields of each span in scope.
+#[derive(Debug, Default)]
+pub(super) struct Logfmt;
+
+impl<S, N> FormatEvent<S, N> for Logfmt
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    /// Formats an event and its span context as a single line.
+    fn format_event(
+        &self,
+        context: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+
+        writer.write_str("ts=")?;
+        SystemTime.format_time(&mut writer)?;
+        write!(
+            writer,
+            " level={} target=",
+            metadata.level().as_str().to_ascii_lowercase()
+        )?;
+        write_value(&mut writer, metadata.target())?;
+
+        let mut visitor = Visitor::new(writer.by_ref(), true);
+        event.record(&mut visitor);
+        visitor.result?;
+
+        if let Some(scope) = context.event_scope() {
+            let mut path = String::new();
+            let mut fields = String::new();
+            for span in scope.from_root() {
+                if !path.is_empty() {
+                    path.push(':');
+                }
+                path.push_str(span.name());
+                if let Some(formatted) = span.extensions().get::<FormattedFields<N>>() {
+                    if !formatted.is_empty() {
+                        write!(fields, " {formatted}")?;
+                    }
+                }
+            }
+            writer.write_str(" span=")?;
+            write_value(&mut writer, &path)?;
+            writer.write_str(&fields)?;
+        }
+
+        writeln!(writer)
+    }
+}
+
+/// Formats span fields as space-separated `key=value` pairs.
+#[derive(Debug, Default)]
+pub(super) struct LogfmtFields;
+
+impl<'writer> FormatFields<'writer> for LogfmtFields {
+    /// Formats a set of span fields.
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut visitor = Visitor::new(writer, false);
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+/// Writes visited fields as `key=value` pairs.
+struct Visitor<'writer> {
+    /// Receives the formatted pairs.
+    writer: Writer<'writer>,
+
+    /// Records whether a pair must be preceded by a space.
+    separate: bool,
+
+    /// Holds the first formatting failure.
+    result: fmt::Result,
+}
+
+impl<'writer> Visitor<'writer> {
+    /// Constructs a visitor, optionally separating the first pair.
+    fn new(writer: Writer<'writer>, separate: bool) -> Self {
+        Self {
+            writer,
+            separate,
+            result: Ok(()),
+        }
+    }
+
+    /// Writes a single pair.
+    fn write_pair(&mut self, field: &Field, value: &str) {
+        if self.result.is_err() {
+            return;
+        }
+        let name = match field.name() {
+            "message" => "msg",
+            name if name.starts_with("log.") => return,
+            name => name.trim_start_matches("r#"),
+        };
+
+        self.result = self.write_raw(name, value);
+    }
+
+    /// Writes a pair whose key has already been normalized.
+    fn write_raw(&mut self, name: &str, value: &str) -> fmt::Result {
+        if self.separate {
+            self.writer.write_char(' ')?;
+        }
+        self.separate = true;
+        write!(self.writer, "{name}=")?;
+        write_value(&mut self.writer, value)
+    }
+}
+
+impl Visit for Visitor<'_> {
+    /// Records a string field without debug quoting.
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.write_pair(field, value);
+    }
+
+    /// Records any other field using its debug representation.
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.write_pair(field, &format!("{value:?}"));
+    }
+}
+
+/// Writes a value, quoting it if it is empty or contains separators.
+fn write_value(writer: &mut Writer<'_>, value: &str) -> fmt::Result {
+    let quote = value.is_empty()
+        || value
+            .chars()
+            .any(|character| matches!(character, ' ' | '=' | '"' | '\\') || character.is_control());
+    if quote {
+        write!(writer, "\"{}\"", value.escape_debug())
+    } else {
+        writer.write_str(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, info_span};
+    use tracing_subscriber::{fmt, layer::SubscriberExt, registry};
+
+    use super::{Logfmt, LogfmtFields};
+    use crate::logging::tests::Buffer;
+
+    /// Writes event and span fields as quoted logfmt pairs.
+    #[test]
+    fn formats_events_with_span_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = registry().with(
+            fmt::layer()
+                .event_format(Logfmt)
+                .fmt_fields(LogfmtFields)
+                .with_writer(move || writer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _request = info_span!("request", method = "GET", path = "/a b").entered();
+            let _handler = info_span!("handler").entered();
+            info!(status = 200, "request \"done\"");
+        });
+
+        let output = buffer.contents();
+        let line = output
+            .strip_suffix('\n')
+            .expect("line should be terminated");
+        let (timestamp, rest) = line.split_once(' ').expect("line should have fields");
+
+        assert!(timestamp.starts_with("ts="));
+        assert_eq!(
+            rest,
+            concat!(
+                "level=info target=twelve::logging::logfmt::tests ",
+                "msg=\"request \\\"done\\\"\" status=200 ",
+                "span=request:handler method=GET path=\"/a b\"",
+            )
+        );
+    }
+}
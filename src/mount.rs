@@ -1,7 +1,9 @@
 //! Builds links and redirects for applications below a reverse-proxy path prefix.
 //!
 //! [`Mount`] reads `X-Script-Name` and prepends it to generated links and
-//! redirects. It does not rewrite request routing.
+//! redirects. It does not rewrite request routing; proxies that forward the
+//! full path can be served by wrapping the router in [`StripPrefixLayer`],
+//! which removes a configured or proxy-reported prefix before routing.
 //!
 //! **Warning:** The reverse proxy must remove client-supplied values before
 //! setting the header.
@@ -14,22 +16,75 @@
 //!     mount.redirect_to("/account")
 //! }
 //! ```
+//!
+//! [`Mount::link`] builds links without panicking: it percent-encodes path
+//! segments, serializes query parameters, appends fragments, and redirects with
+//! `303 See Other`, `307 Temporary Redirect` or `308 Permanent Redirect`.
+//!
+//! [`Mount::url_for`] and [`Mount::link_for`] build links to typed routes
+//! declared with [`crate::route`].
+//!
+//! [`Mount::cookie_path`] scopes cookies to the mount prefix, so that session
+//! cookies are not sent to sibling applications on the same domain. With the
+//! `cookie` feature, `Mount::cookie` starts a cookie with that path and sets
+//! `Secure` if clients reach the application over HTTPS.
+//!
+//! [`Mount::absolute`] builds absolute URLs for redirect URIs, emails and
+//! canonical links. They start with the [`BaseUrl`] inserted as a request
+//! extension, usually [`Core::public_url`](crate::config::Core::public_url).
+//! Without one, the scheme, host and prefix reported by a trusted proxy are
+//! used, as described in [`crate::proxy`]. If neither is available, an error
+//! is returned rather than trusting the client's `Host` header.
+//!
+//! ```
+//! use axum::{http::StatusCode, Extension, Router, routing::get};
+//! use twelve::{config::BaseUrl, mount::Mount};
+//!
+//! async fn reset_link(mount: Mount) -> Result<String, StatusCode> {
+//!     mount
+//!         .absolute("/password/reset")
+//!         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let public_url: BaseUrl = "https://example.com/app".parse()?;
+//! let app: Router = Router::new()
+//!     .route("/reset-link", get(reset_link))
+//!     .layer(Extension(public_url));
+//! # let _ = app;
+//! # Ok(())
+//! # }
+//! ```
 
 use axum::{
     extract::FromRequestParts,
     http::{
         request::Parts,
-        uri::{self},
-        StatusCode, Uri,
+        uri::{InvalidUri, Scheme},
+        Extensions, HeaderMap, StatusCode,
     },
     response::Redirect,
 };
+use thiserror::Error;
+
+use self::strip::Stripped;
+pub use self::{
+    link::Link,
+    strip::{StripPrefix, StripPrefixLayer},
+};
+use crate::{config::BaseUrl, proxy::Forwarded, route::Route};
+
+mod link;
+mod strip;
 
 /// Provides request-aware links for applications below a proxy path prefix.
 #[derive(Debug)]
 pub struct Mount {
     /// The absolute path on the domain that the app is running under.
-    script_name: Option<String>,
+    pub(crate) script_name: Option<String>,
+
+    /// The scheme, authority and path prefix of absolute URLs, if known.
+    pub(crate) origin: Option<String>,
 }
 
 impl Mount {
@@ -40,44 +95,137 @@ impl Mount {
     ///
     /// # Panics
     ///
-    /// Will panic if generated Uris are invalid.
-    // TODO: Log warning, generate different Uri, or ensure this can never fail?
+    /// Will panic if generated Uris are invalid. Use [`Mount::link`] to handle
+    /// invalid paths as errors.
     pub fn internal<S: AsRef<str>>(&self, path: S) -> String {
-        let mut parts: uri::Parts = Default::default();
-
-        if let Some(ref script_name) = self.script_name {
-            let path = format!(
-                "{}/{}",
-                script_name.trim_end_matches('/'),
-                path.as_ref().trim_start_matches('/'),
-            );
-            parts.path_and_query = Some(path.parse().expect("should not fail to parse"));
-        } else {
-            parts.path_and_query = Some(
-                path.as_ref()
-                    .parse()
-                    .expect("tried to generate invalid Uri"),
-            );
-        }
-
-        // TODO: Set scheme from reverse proxy headers if available.
+        self.link(path.as_ref())
+            .build()
+            .expect("tried to generate invalid Uri")
+    }
 
-        Uri::from_parts(parts)
-            .expect("should not fail to construct relative uri")
-            .to_string()
+    /// Starts building a link to `path` below the mount prefix.
+    ///
+    /// `path` is used verbatim; [`Link::segment`] appends encoded segments.
+    pub fn link(&self, path: &str) -> Link<'_> {
+        Link::new(self, path)
     }
 
     #[inline(always)]
     pub fn redirect_to(&self, path: &str) -> Redirect {
         Redirect::to(&self.internal(path))
     }
-}
 
-impl<S: Send + Sync> FromRequestParts<S> for Mount {
-    type Rejection = StatusCode;
+    /// Constructs a relative URL for a typed route.
+    ///
+    /// Path parameters are percent-encoded into their segments and other fields
+    /// become query parameters. Fails if a parameter of the path pattern is
+    /// missing or the result is not a valid URL.
+    pub fn url_for<R: Route>(&self, route: &R) -> Result<String, Error> {
+        self.link_for(route)?.build()
+    }
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let script_name = if let Some(script_name_header) = parts.headers.get("X-Script-Name") {
+    /// Starts building a link to a typed route; see [`Mount::url_for`].
+    pub fn link_for<R: Route>(&self, route: &R) -> Result<Link<'_>, Error> {
+        Link::for_route(self, route)
+    }
+
+    /// Constructs an absolute URL including scheme and host.
+    ///
+    /// Exactly one slash separates the public base URL from the supplied
+    /// path. Fails if no public base URL is configured and no trusted proxy
+    /// reported the host, or if the result is not a valid URL.
+    pub fn absolute<S: AsRef<str>>(&self, path: S) -> Result<String, Error> {
+        self.link(path.as_ref()).absolute()
+    }
+
+    /// Redirects to an absolute URL; see [`Mount::absolute`].
+    pub fn absolute_redirect_to(&self, path: &str) -> Result<Redirect, Error> {
+        self.absolute(path).map(|url| Redirect::to(&url))
+    }
+
+    /// Redirects with `303 See Other`, failing instead of panicking on an
+    /// invalid path.
+    pub fn see_other(&self, path: &str) -> Result<Redirect, Error> {
+        self.link(path).redirect()
+    }
+
+    /// Redirects with `307 Temporary Redirect`, preserving the method.
+    pub fn temporary_redirect(&self, path: &str) -> Result<Redirect, Error> {
+        self.link(path).temporary_redirect()
+    }
+
+    /// Redirects with `308 Permanent Redirect`, preserving the method.
+    pub fn permanent_redirect(&self, path: &str) -> Result<Redirect, Error> {
+        self.link(path).permanent_redirect()
+    }
+
+    /// Returns the cookie path that scopes cookies to the mount prefix.
+    ///
+    /// Returns `/` if the application is not mounted below a prefix.
+    #[must_use]
+    pub fn cookie_path(&self) -> &str {
+        match self
+            .script_name
+            .as_deref()
+            .map(|name| name.trim_end_matches('/'))
+        {
+            Some(prefix) if !prefix.is_empty() => prefix,
+            _ => "/",
+        }
+    }
+
+    /// Reports whether clients reach the application over HTTPS.
+    ///
+    /// Relies on the origin of absolute URLs, so it is `false` if neither a
+    /// public base URL nor a trusted proxy identified the external scheme.
+    #[must_use]
+    pub fn is_secure(&self) -> bool {
+        self.origin
+            .as_deref()
+            .is_some_and(|origin| origin.starts_with("https://"))
+    }
+
+    /// Starts building a cookie scoped to the mount prefix.
+    ///
+    /// Sets `Path` to [`Mount::cookie_path`] and `Secure` if
+    /// [`Mount::is_secure`]. Other attributes are left to the caller.
+    #[cfg(feature = "cookie")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookie")))]
+    pub fn cookie<'c, N, V>(&self, name: N, value: V) -> cookie::CookieBuilder<'c>
+    where
+        N: Into<std::borrow::Cow<'c, str>>,
+        V: Into<std::borrow::Cow<'c, str>>,
+    {
+        cookie::Cookie::build((name, value))
+            .path(self.cookie_path().to_owned())
+            .secure(self.is_secure())
+    }
+
+    /// Prepends the external mount prefix to `path` without validating it.
+    ///
+    /// Exactly one slash separates the prefix from the path.
+    pub(crate) fn prefixed(&self, path: &str) -> String {
+        match self.script_name {
+            Some(ref script_name) => format!(
+                "{}/{}",
+                script_name.trim_end_matches('/'),
+                path.trim_start_matches('/'),
+            ),
+            None => path.to_owned(),
+        }
+    }
+
+    /// Reads the mount prefix from request headers, preferring the prefix
+    /// recorded by [`StripPrefix`].
+    ///
+    /// Fails with `502 Bad Gateway` if the proxy sent an invalid prefix.
+    pub(crate) fn from_headers(
+        headers: &HeaderMap,
+        extensions: &Extensions,
+    ) -> Result<Self, StatusCode> {
+        let script_name = if let Some(Stripped(prefix)) = extensions.get() {
+            Some(prefix.clone())
+        } else if let Some(script_name_header) = headers.get("X-Script-Name") {
             Some(
                 script_name_header
                     .to_str()
@@ -88,17 +236,140 @@ impl<S: Send + Sync> FromRequestParts<S> for Mount {
             None
         };
 
-        Ok(Mount { script_name })
+        Ok(Mount {
+            script_name,
+            origin: None,
+        })
+    }
+
+    /// Reads the mount prefix and the origin of absolute URLs from a request.
+    ///
+    /// A trusted proxy's `X-Forwarded-Prefix` is used if `X-Script-Name` is
+    /// absent.
+    pub(crate) fn from_parts(parts: &Parts) -> Result<Self, StatusCode> {
+        let mut mount = Self::from_headers(&parts.headers, &parts.extensions)?;
+        let forwarded = Forwarded::from_trusted_proxy(parts);
+        if mount.script_name.is_none() {
+            mount.script_name = forwarded
+                .as_ref()
+                .and_then(|forwarded| forwarded.prefix.clone());
+        }
+
+        mount.origin = if let Some(base_url) = parts.extensions.get::<BaseUrl>() {
+            Some(base_url.as_str().to_owned())
+        } else {
+            forwarded.and_then(|forwarded| {
+                let host = forwarded.host?;
+                let default_port = if forwarded.scheme == Scheme::HTTPS {
+                    443
+                } else {
+                    80
+                };
+                let port = match forwarded.port {
+                    Some(port) if port != default_port => format!(":{port}"),
+                    _ => String::new(),
+                };
+                let prefix = mount.script_name.as_deref().unwrap_or_default();
+
+                Some(format!(
+                    "{}://{host}{port}{}",
+                    forwarded.scheme,
+                    prefix.trim_end_matches('/')
+                ))
+            })
+        };
+
+        Ok(mount)
     }
 }
 
+impl<S: Send + Sync> FromRequestParts<S> for Mount {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_parts(parts)
+    }
+}
+
+/// Describes a failure to build a URL.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Indicates that neither a public base URL nor a trusted proxy identified
+    /// the external origin.
+    #[error(
+        "cannot build an absolute URL: no public base URL is configured and no trusted proxy \
+         reported the host"
+    )]
+    UnknownOrigin,
+
+    /// Indicates that query parameters could not be serialized.
+    #[error("failed to serialize query parameters")]
+    Query {
+        /// Provides the underlying serialization error.
+        #[source]
+        source: serde_urlencoded::ser::Error,
+    },
+
+    /// Indicates that a route value lacks a parameter of its path pattern.
+    #[error("route parameter {name} is missing")]
+    MissingParameter {
+        /// Names the missing parameter.
+        name: String,
+    },
+
+    /// Indicates that the generated URL is invalid.
+    #[error("generated URL {url} is invalid")]
+    InvalidUrl {
+        /// Provides the rejected URL.
+        url: String,
+
+        /// Provides the underlying URL error.
+        #[source]
+        source: InvalidUri,
+    },
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Mount;
+    use std::net::SocketAddr;
+
+    use axum::{
+        extract::ConnectInfo,
+        http::{request::Parts, Request},
+    };
+    use serde::Deserialize;
+    use toml::Value;
+
+    use super::{Error, Mount};
+    use crate::config::{BaseUrl, TrustedProxies};
+
+    /// Builds request parts from `peer` with the given headers.
+    fn parts(peer: &str, headers: &[(&str, &str)]) -> Parts {
+        let mut request = Request::builder().uri("/");
+        for &(name, value) in headers {
+            request = request.header(name, value);
+        }
+        let (mut parts, ()) = request
+            .body(())
+            .expect("request should be valid")
+            .into_parts();
+        parts.extensions.insert(ConnectInfo(
+            peer.parse::<SocketAddr>().expect("peer should parse"),
+        ));
+        parts.extensions.insert(
+            TrustedProxies::deserialize(Value::Array(vec![Value::from("10.0.0.0/8")]))
+                .expect("trusted proxies should deserialize"),
+        );
+
+        parts
+    }
 
     #[test]
     fn internal_url_construction_without_reverse_proxy() {
-        let mount = Mount { script_name: None };
+        let mount = Mount {
+            script_name: None,
+            origin: None,
+        };
 
         assert_eq!(mount.internal("/foo/bar"), "/foo/bar");
     }
@@ -107,9 +378,85 @@ mod tests {
     fn internal_url_construction_with_reverse_proxy() {
         let mount = Mount {
             script_name: Some("/sub/dir///".to_owned()),
+            origin: None,
         };
 
         assert_eq!(mount.internal("foo/bar"), "/sub/dir/foo/bar");
         assert_eq!(mount.internal("///foo/bar"), "/sub/dir/foo/bar");
     }
+
+    /// Prefers the configured public base URL for absolute URLs.
+    #[test]
+    fn absolute_urls_from_public_base_url() {
+        let mut parts = parts("10.0.0.1:5000", &[("x-forwarded-host", "proxy.example")]);
+        parts.extensions.insert(
+            "https://example.com/app/"
+                .parse::<BaseUrl>()
+                .expect("base URL should parse"),
+        );
+        let mount = Mount::from_parts(&parts).expect("mount should be extracted");
+
+        assert_eq!(
+            mount.absolute("/reset").expect("URL should be built"),
+            "https://example.com/app/reset"
+        );
+    }
+
+    /// Scopes cookies to the prefix and marks them secure behind HTTPS.
+    #[test]
+    fn cookie_scoping() {
+        let headers = [
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "example.com"),
+            ("x-script-name", "/app/"),
+        ];
+        let mount = Mount::from_parts(&parts("10.0.0.1:5000", &headers))
+            .expect("mount should be extracted");
+        assert_eq!(mount.cookie_path(), "/app");
+        assert!(mount.is_secure());
+
+        let untrusted = Mount::from_parts(&parts("192.0.2.1:5000", &headers))
+            .expect("mount should be extracted");
+        assert_eq!(untrusted.cookie_path(), "/app");
+        assert!(!untrusted.is_secure());
+
+        let root = Mount {
+            script_name: Some("/".to_owned()),
+            origin: None,
+        };
+        assert_eq!(root.cookie_path(), "/");
+
+        #[cfg(feature = "cookie")]
+        assert_eq!(
+            mount.cookie("session", "abc").build().to_string(),
+            "session=abc; Secure; Path=/app"
+        );
+    }
+
+    /// Falls back to trusted proxy headers and refuses to guess otherwise.
+    #[test]
+    fn absolute_urls_from_trusted_proxy() {
+        let headers = [
+            ("host", "app.internal:8080"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "example.com:8443"),
+            ("x-forwarded-prefix", "/app/"),
+        ];
+        let mount = Mount::from_parts(&parts("10.0.0.1:5000", &headers))
+            .expect("mount should be extracted");
+
+        assert_eq!(mount.internal("/reset"), "/app/reset");
+        assert_eq!(
+            mount.absolute("reset").expect("URL should be built"),
+            "https://example.com:8443/app/reset"
+        );
+
+        let untrusted = Mount::from_parts(&parts("192.0.2.1:5000", &headers))
+            .expect("mount should be extracted");
+        assert!(matches!(
+            untrusted.absolute("/reset"),
+            Err(Error::UnknownOrigin)
+        ));
+        assert_eq!(untrusted.internal("/reset"), "/reset");
+    }
 }
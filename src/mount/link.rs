@@ -0,0 +1,354 @@
+//! Builds links with encoded segments, query parameters and fragments.
+
+use axum::{
+    http::{uri::PathAndQuery, Uri},
+    response::Redirect,
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC};
+use serde::Serialize;
+
+use super::{Error, Mount};
+use crate::route::{self, Route};
+
+/// Encodes every character of a path segment except unreserved ones.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Encodes the characters that may not appear in a fragment.
+const FRAGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>')
+    .add(b'`');
+
+/// Builds a link below the application's mount prefix.
+///
+/// Created by [`Mount::link`]. Failures are deferred until the link is built,
+/// so calls can be chained.
+///
+/// ```
+/// use axum::response::Redirect;
+/// use serde::Serialize;
+/// use twelve::mount::{self, Mount};
+///
+/// #[derive(Serialize)]
+/// struct Search<'a> {
+///     q: &'a str,
+///     page: u32,
+/// }
+///
+/// async fn search(mount: Mount) -> Result<Redirect, String> {
+///     mount
+///         .link("/users")
+///         .segment("jane doe")
+///         .query(&Search { q: "a&b", page: 2 })
+///         .fragment("results")
+///         .redirect()
+///         .map_err(|error: mount::Error| error.to_string())
+/// }
+/// ```
+#[derive(Debug)]
+#[must_use]
+pub struct Link<'a> {
+    /// Provides the mount prefix and origin.
+    mount: &'a Mount,
+
+    /// Holds the path relative to the mount prefix.
+    path: String,
+
+    /// Holds the encoded query, or the first serialization failure.
+    query: Result<String, serde_urlencoded::ser::Error>,
+
+    /// Holds the encoded fragment.
+    fragment: Option<String>,
+}
+
+impl<'a> Link<'a> {
+    /// Starts a link at `path`, which is used verbatim.
+    pub(super) fn new(mount: &'a Mount, path: &str) -> Self {
+        Self {
+            mount,
+            path: path.to_owned(),
+            query: Ok(String::new()),
+            fragment: None,
+        }
+    }
+
+    /// Starts a link to `route`, filling in its path parameters.
+    ///
+    /// Fields not named in the path pattern become query parameters.
+    pub(super) fn for_route<R: Route>(mount: &'a Mount, route: &R) -> Result<Self, Error> {
+        let encoded =
+            serde_urlencoded::to_string(route).map_err(|source| Error::Query { source })?;
+        let mut parameters: Vec<(String, String)> = serde_urlencoded::from_str(&encoded)
+            .expect("serialized parameters should deserialize as pairs");
+
+        let mut path = String::new();
+        for segment in R::PATH.split('/').skip(1) {
+            path.push('/');
+            let Some((name, wildcard)) = route::parameter(segment) else {
+                path.push_str(segment);
+                continue;
+            };
+            let index = parameters
+                .iter()
+                .position(|(parameter, _)| parameter == name)
+                .ok_or_else(|| Error::MissingParameter {
+                    name: name.to_owned(),
+                })?;
+            let (_, value) = parameters.remove(index);
+            if wildcard {
+                let segments: Vec<_> = value
+                    .split('/')
+                    .map(|segment| utf8_percent_encode(segment, SEGMENT).to_string())
+                    .collect();
+                path.push_str(&segments.join("/"));
+            } else {
+                path.extend(utf8_percent_encode(&value, SEGMENT));
+            }
+        }
+
+        Ok(Self::new(mount, &path).query(&parameters))
+    }
+
+    /// Appends a percent-encoded path segment.
+    ///
+    /// Every character except ASCII letters, digits, `-`, `.`, `_` and `~` is
+    /// encoded, including `/`.
+    pub fn segment(mut self, segment: impl AsRef<str>) -> Self {
+        if !self.path.ends_with('/') {
+            self.path.push('/');
+        }
+        self.path
+            .extend(utf8_percent_encode(segment.as_ref(), SEGMENT));
+        self
+    }
+
+    /// Appends query parameters serialized from `parameters`.
+    ///
+    /// `parameters` must serialize as a map or a sequence of pairs, such as a
+    /// struct or `[("key", "value")]`. Repeated calls append further
+    /// parameters.
+    pub fn query<T: Serialize + ?Sized>(mut self, parameters: &T) -> Self {
+        if let Ok(query) = &mut self.query {
+            match serde_urlencoded::to_string(parameters) {
+                Ok(encoded) if encoded.is_empty() => {}
+                Ok(encoded) => {
+                    if !query.is_empty() {
+                        query.push('&');
+                    }
+                    query.push_str(&encoded);
+                }
+                Err(source) => self.query = Err(source),
+            }
+        }
+        self
+    }
+
+    /// Sets the fragment, percent-encoding characters not allowed in it.
+    pub fn fragment(mut self, fragment: impl AsRef<str>) -> Self {
+        self.fragment = Some(utf8_percent_encode(fragment.as_ref(), FRAGMENT).to_string());
+        self
+    }
+
+    /// Builds a relative URL including the mount prefix.
+    pub fn build(self) -> Result<String, Error> {
+        let path = self.mount.prefixed(&self.path);
+        self.finish(path, false)
+    }
+
+    /// Builds an absolute URL; see [`Mount::absolute`].
+    pub fn absolute(self) -> Result<String, Error> {
+        let origin = self.mount.origin.as_deref().ok_or(Error::UnknownOrigin)?;
+        let url = format!("{origin}/{}", self.path.trim_start_matches('/'));
+        self.finish(url, true)
+    }
+
+    /// Redirects to the link with `303 See Other`.
+    pub fn redirect(self) -> Result<Redirect, Error> {
+        self.build().map(|url| Redirect::to(&url))
+    }
+
+    /// Redirects to the link with `307 Temporary Redirect`.
+    pub fn temporary_redirect(self) -> Result<Redirect, Error> {
+        self.build().map(|url| Redirect::temporary(&url))
+    }
+
+    /// Redirects to the link with `308 Permanent Redirect`.
+    pub fn permanent_redirect(self) -> Result<Redirect, Error> {
+        self.build().map(|url| Redirect::permanent(&url))
+    }
+
+    /// Appends the query and fragment to `base` and validates the result.
+    fn finish(self, base: String, absolute: bool) -> Result<String, Error> {
+        let query = self.query.map_err(|source| Error::Query { source })?;
+        let mut url = base;
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+
+        let valid = if absolute {
+            url.parse::<Uri>().map(drop)
+        } else {
+            url.parse::<PathAndQuery>().map(drop)
+        };
+        if let Err(source) = valid {
+            return Err(Error::InvalidUrl { url, source });
+        }
+
+        if let Some(fragment) = self.fragment {
+            url.push('#');
+            url.push_str(&fragment);
+        }
+
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::{header::LOCATION, StatusCode},
+        response::IntoResponse,
+    };
+    use serde::Serialize;
+
+    use crate::{
+        mount::{Error, Mount},
+        route::Route,
+    };
+
+    /// Provides query parameters for tests.
+    #[derive(Serialize)]
+    struct Search {
+        /// Provides a value requiring encoding.
+        q: &'static str,
+
+        /// Provides a numeric value.
+        page: u32,
+    }
+
+    /// Describes a route with a segment, a wildcard, and a query parameter.
+    #[derive(Serialize)]
+    struct File {
+        /// Identifies the owner.
+        owner: &'static str,
+
+        /// Provides the file path.
+        path: &'static str,
+
+        /// Selects a revision.
+        revision: u32,
+    }
+
+    impl Route for File {
+        const PATH: &'static str = "/users/{owner}/files/{*path}";
+    }
+
+    /// Describes a route whose value lacks a path parameter.
+    #[derive(Serialize)]
+    struct Broken {
+        /// Names a parameter absent from the pattern.
+        other: u32,
+    }
+
+    impl Route for Broken {
+        const PATH: &'static str = "/broken/{id}";
+    }
+
+    /// Constructs a mount below `/app`.
+    fn mount() -> Mount {
+        Mount {
+            script_name: Some("/app/".to_owned()),
+            origin: Some("https://example.com/app".to_owned()),
+        }
+    }
+
+    /// Encodes segments, queries and fragments.
+    #[test]
+    fn builds_encoded_links() {
+        let mount = mount();
+        let link = |path| {
+            mount
+                .link(path)
+                .segment("jane doe/é")
+                .query(&Search { q: "a&b", page: 2 })
+                .query(&[("sort", "name")])
+                .fragment("top section")
+        };
+
+        assert_eq!(
+            link("/users").build().expect("link should build"),
+            "/app/users/jane%20doe%2F%C3%A9?q=a%26b&page=2&sort=name#top%20section"
+        );
+        assert_eq!(
+            link("users/").absolute().expect("link should build"),
+            "https://example.com/app/users/jane%20doe%2F%C3%A9?q=a%26b&page=2&sort=name#top%20section"
+        );
+    }
+
+    /// Reports invalid paths and queries instead of panicking.
+    #[test]
+    fn reports_invalid_links() {
+        let mount = mount();
+
+        assert!(matches!(
+            mount.link("/bad path").build(),
+            Err(Error::InvalidUrl { .. })
+        ));
+        assert!(matches!(
+            mount.link("/users").query(&"not a map").build(),
+            Err(Error::Query { .. })
+        ));
+        assert!(mount.see_other("/bad path").is_err());
+    }
+
+    /// Fills typed route parameters and moves other fields into the query.
+    #[test]
+    fn builds_route_urls() {
+        let mount = mount();
+        let file = File {
+            owner: "jane doe",
+            path: "docs/a b.txt",
+            revision: 3,
+        };
+
+        assert_eq!(
+            mount.url_for(&file).expect("URL should build"),
+            "/app/users/jane%20doe/files/docs/a%20b.txt?revision=3"
+        );
+        assert!(matches!(
+            mount.url_for(&Broken { other: 1 }),
+            Err(Error::MissingParameter { name }) if name == "id"
+        ));
+    }
+
+    /// Redirects with the requested status.
+    #[test]
+    fn redirects_with_status() {
+        let mount = mount();
+        let cases = [
+            (mount.see_other("/a"), StatusCode::SEE_OTHER),
+            (
+                mount.temporary_redirect("/a"),
+                StatusCode::TEMPORARY_REDIRECT,
+            ),
+            (
+                mount.permanent_redirect("/a"),
+                StatusCode::PERMANENT_REDIRECT,
+            ),
+        ];
+
+        for (redirect, status) in cases {
+            let response = redirect.expect("redirect should build").into_response();
+            assert_eq!(response.status(), status);
+            assert_eq!(response.headers()[LOCATION], "/app/a");
+        }
+    }
+}
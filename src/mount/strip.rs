@@ -0,0 +1,291 @@
+//! Strips the mount prefix from request paths before routing.
+
+use std::{
+    future::{ready, Future},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::http::{uri::PathAndQuery, Request, Response, StatusCode, Uri};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::Mount;
+
+/// Records the prefix removed by [`StripPrefix`] as a request extension.
+#[derive(Clone, Debug)]
+pub(super) struct Stripped(pub(super) String);
+
+/// Determines which prefix is stripped.
+#[derive(Clone, Debug)]
+enum Source {
+    /// Strips a fixed prefix, normalized to start with and not end in `/`.
+    Fixed(String),
+
+    /// Strips the prefix reported by the proxy, as read by [`Mount`].
+    Headers,
+}
+
+/// Wraps services in [`StripPrefix`].
+///
+/// Axum runs layers added with [`Router::layer`](axum::Router::layer) after
+/// routing, so this layer has to wrap the whole router instead:
+///
+/// ```
+/// use axum::{extract::Request, routing::get, Router, ServiceExt};
+/// use tower_layer::Layer;
+/// use twelve::mount::{Mount, StripPrefixLayer};
+///
+/// async fn index(mount: Mount) -> String {
+///     // Returns `/sub/dir/account` for a request to `/sub/dir/`.
+///     mount.internal("/account")
+/// }
+///
+/// let router: Router = Router::new().route("/", get(index));
+/// let app = StripPrefixLayer::new("/sub/dir").layer(router);
+/// let make_service = ServiceExt::<Request>::into_make_service(app);
+/// # let _ = make_service;
+/// ```
+#[derive(Clone, Debug)]
+pub struct StripPrefixLayer {
+    /// Determines the stripped prefix.
+    source: Source,
+}
+
+impl StripPrefixLayer {
+    /// Strips a fixed `prefix`, such as `/sub/dir`.
+    ///
+    /// A missing leading slash is added and trailing slashes are ignored. An
+    /// empty prefix leaves requests unchanged.
+    #[must_use]
+    pub fn new(prefix: impl AsRef<str>) -> Self {
+        let prefix = prefix.as_ref().trim_matches('/');
+        let prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            format!("/{prefix}")
+        };
+
+        Self {
+            source: Source::Fixed(prefix),
+        }
+    }
+
+    /// Strips the prefix reported by the proxy in `X-Script-Name`, or in the
+    /// `X-Forwarded-Prefix` of a trusted proxy.
+    ///
+    /// Requests without either header are left unchanged.
+    #[must_use]
+    pub fn from_headers() -> Self {
+        Self {
+            source: Source::Headers,
+        }
+    }
+}
+
+impl<S> Layer<S> for StripPrefixLayer {
+    type Service = StripPrefix<S>;
+
+    /// Wraps `inner` in prefix stripping.
+    fn layer(&self, inner: S) -> Self::Service {
+        StripPrefix {
+            inner,
+            source: self.source.clone(),
+        }
+    }
+}
+
+/// Removes the mount prefix from request paths and rejects requests outside it.
+///
+/// The removed prefix is recorded so that [`Mount`] keeps generating links
+/// that include it. Requests whose path is not the prefix or below it are
+/// answered with `404 Not Found`; an invalid prefix header is answered with
+/// `502 Bad Gateway`.
+#[derive(Clone, Debug)]
+pub struct StripPrefix<S> {
+    /// Handles requests below the prefix.
+    inner: S,
+
+    /// Determines the stripped prefix.
+    source: Source,
+}
+
+impl<S, B, R> Service<Request<B>> for StripPrefix<S>
+where
+    S: Service<Request<B>, Response = Response<R>>,
+    S::Error: Send + 'static,
+    S::Future: Send + 'static,
+    R: Default + Send + 'static,
+{
+    type Response = Response<R>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    /// Reports whether the inner service is ready.
+    fn poll_ready(&mut self, context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(context)
+    }
+
+    /// Strips the prefix and forwards the request, or rejects it.
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+        let prefix = match &self.source {
+            Source::Fixed(prefix) => prefix.clone(),
+            Source::Headers => match Mount::from_parts(&parts) {
+                Ok(mount) => mount
+                    .script_name
+                    .map(|prefix| prefix.trim_end_matches('/').to_owned())
+                    .unwrap_or_default(),
+                Err(status) => return reject(status),
+            },
+        };
+
+        if !prefix.is_empty() {
+            let Some(uri) = strip(&parts.uri, &prefix) else {
+                return reject(StatusCode::NOT_FOUND);
+            };
+            parts.uri = uri;
+            parts.extensions.insert(Stripped(prefix));
+        }
+
+        Box::pin(self.inner.call(Request::from_parts(parts, body)))
+    }
+}
+
+/// Removes `prefix` from the path of `uri`, keeping the query.
+///
+/// Returns `None` if the path is neither `prefix` nor below it.
+fn strip(uri: &Uri, prefix: &str) -> Option<Uri> {
+    let rest = uri.path().strip_prefix(prefix)?;
+    let path = match rest {
+        "" => "/",
+        rest if rest.starts_with('/') => rest,
+        _ => return None,
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_owned(),
+    };
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(
+        path_and_query
+            .parse::<PathAndQuery>()
+            .expect("a suffix of a valid path should be valid"),
+    );
+    Some(Uri::from_parts(parts).expect("replacing the path should keep the URI valid"))
+}
+
+/// Answers a request with an empty response carrying `status`.
+fn reject<R, E>(status: StatusCode) -> Pin<Box<dyn Future<Output = Result<Response<R>, E>> + Send>>
+where
+    R: Default + Send + 'static,
+    E: Send + 'static,
+{
+    let mut response = Response::new(R::default());
+    *response.status_mut() = status;
+    Box::pin(ready(Ok(response)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+    use tower_layer::Layer;
+
+    use super::StripPrefixLayer;
+    use crate::mount::Mount;
+
+    /// Sends a request for `uri` through `layer` and returns status and body.
+    async fn send(
+        layer: StripPrefixLayer,
+        uri: &str,
+        headers: &[(&str, &str)],
+    ) -> (StatusCode, String) {
+        let router: Router = Router::new()
+            .route(
+                "/",
+                get(|mount: Mount| async move { mount.internal("/account") }),
+            )
+            .route(
+                "/items",
+                get(|uri: axum::http::Uri| async move { uri.to_string() }),
+            );
+        let mut request = Request::builder().uri(uri);
+        for &(name, value) in headers {
+            request = request.header(name, value);
+        }
+        let response = layer
+            .layer(router)
+            .oneshot(
+                request
+                    .body(Body::empty())
+                    .expect("request should be valid"),
+            )
+            .await
+            .expect("router should be infallible");
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("body should be readable")
+            .to_bytes();
+
+        (
+            status,
+            String::from_utf8(body.to_vec()).expect("body should be UTF-8"),
+        )
+    }
+
+    /// Routes below a fixed prefix and keeps it in generated links.
+    #[tokio::test]
+    async fn strips_fixed_prefix() {
+        let layer = || StripPrefixLayer::new("sub/dir/");
+
+        assert_eq!(
+            send(layer(), "/sub/dir", &[]).await,
+            (StatusCode::OK, "/sub/dir/account".to_owned())
+        );
+        assert_eq!(
+            send(layer(), "/sub/dir/items?page=2", &[]).await,
+            (StatusCode::OK, "/items?page=2".to_owned())
+        );
+        assert_eq!(
+            send(layer(), "/sub/directory", &[]).await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(send(layer(), "/items", &[]).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(
+            send(StripPrefixLayer::new("/"), "/items", &[]).await.0,
+            StatusCode::OK
+        );
+    }
+
+    /// Strips the prefix reported by the proxy, if any.
+    #[tokio::test]
+    async fn strips_header_prefix() {
+        let headers = [("x-script-name", "/app/")];
+
+        assert_eq!(
+            send(StripPrefixLayer::from_headers(), "/app/", &headers).await,
+            (StatusCode::OK, "/app/account".to_owned())
+        );
+        assert_eq!(
+            send(StripPrefixLayer::from_headers(), "/other", &headers)
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(StripPrefixLayer::from_headers(), "/", &[]).await,
+            (StatusCode::OK, "/account".to_owned())
+        );
+    }
+}
@@ -1,7 +1,8 @@
 //! Provides classic HTML error pages and POST-Redirect-GET responses for Axum.
 //!
 //! [`ErrorPage`] renders unstyled HTML with a status heading. It includes the
-//! error chain for user-visible errors or when `DEBUG` is enabled.
+//! error chain for user-visible errors or when `DEBUG` is enabled, and the
+//! request ID assigned by [`TraceLayer`](crate::request::TraceLayer).
 //! [`RedirectOnSuccess`] represents either a page response or a success
 //! redirect. JSON APIs should define their own error contract.
 //!
@@ -25,6 +26,8 @@ use axum::{
 use html_escape::encode_text;
 use thiserror::Error;
 
+use crate::request::RequestId;
+
 /// Whether or not to allow users that are being returned an error detailed insight.
 static DETAILED_ERRORS: OnceLock<bool> = OnceLock::new();
 
@@ -32,6 +35,7 @@ static DETAILED_ERRORS: OnceLock<bool> = OnceLock::new();
 ///
 /// Renders a simple HTML error page with the status code and error chain.
 /// Error details are shown if `DEBUG=1` or `DEBUG=true`, or if the error is user-visible.
+/// The request ID is shown when the request is traced by [`TraceLayer`](crate::request::TraceLayer).
 #[derive(Debug)]
 pub struct ErrorPage<E>(E);
 
@@ -66,6 +70,13 @@ where
             }
         }
 
+        if let Some(request_id) = RequestId::current() {
+            html.push_str(&format!(
+                "<p>Request ID: <code>{}</code></p>",
+                encode_text(request_id.as_str())
+            ));
+        }
+
         html.push_str("</body></html>");
         html
     }
@@ -0,0 +1,456 @@
+//! Derives a request's external origin from reverse proxy headers.
+//!
+//! [`Forwarded`] reports the scheme, host, port, client IP address and path
+//! prefix of a request as seen by the client. They are read from the RFC 7239
+//! `Forwarded` header or, in its absence, from `X-Forwarded-Proto`,
+//! `X-Forwarded-Host`, `X-Forwarded-Port` and `X-Forwarded-For`; the prefix is
+//! read from `X-Forwarded-Prefix`.
+//!
+//! The headers are only honored when the immediate peer, taken from
+//! [`ConnectInfo`](axum::extract::ConnectInfo), is listed in the
+//! [`TrustedProxies`] inserted as a request extension. Otherwise they are
+//! ignored and the values describe the direct connection, so client-supplied
+//! headers never need to be stripped by the proxy. Without the extension, no
+//! peer is trusted.
+//!
+//! ```no_run
+//! use axum::{routing::get, Extension, Router};
+//! use twelve::{config::Core, proxy::Forwarded};
+//!
+//! async fn whoami(forwarded: Forwarded) -> String {
+//!     format!("{:?} via {}", forwarded.client, forwarded.scheme)
+//! }
+//!
+//! fn app(core: &Core) -> Router {
+//!     Router::new()
+//!         .route("/whoami", get(whoami))
+//!         .layer(Extension(core.trusted_proxies.clone()))
+//! }
+//! ```
+
+use std::{convert::Infallible, net::IpAddr};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{
+        header::{FORWARDED, HOST},
+        request::Parts,
+        uri::Scheme,
+        HeaderMap, Uri,
+    },
+};
+
+use crate::{config::TrustedProxies, listener::Address};
+
+/// Names the header carrying the original scheme.
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// Names the header carrying the original host.
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// Names the header carrying the original port.
+const X_FORWARDED_PORT: &str = "x-forwarded-port";
+
+/// Names the header carrying the client and proxy addresses.
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Names the header carrying the external path prefix.
+const X_FORWARDED_PREFIX: &str = "x-forwarded-prefix";
+
+/// Describes a request as the client sent it, before any reverse proxy.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Forwarded {
+    /// Identifies the scheme used by the client.
+    pub scheme: Scheme,
+
+    /// Identifies the requested host without its port.
+    pub host: Option<String>,
+
+    /// Identifies the requested port if it was given explicitly.
+    pub port: Option<u16>,
+
+    /// Identifies the client, or `None` if it is unknown or obfuscated.
+    pub client: Option<IpAddr>,
+
+    /// Provides the external path prefix set by the proxy.
+    pub prefix: Option<String>,
+}
+
+impl Forwarded {
+    /// Derives the external origin from request parts.
+    pub(crate) fn from_parts(parts: &Parts) -> Self {
+        let peer = Address::from_connect_info(&parts.extensions);
+        let trusted = parts.extensions.get::<TrustedProxies>();
+
+        Self::from_request(&parts.headers, &parts.uri, peer.as_ref(), trusted)
+    }
+
+    /// Derives the external origin if the peer is a trusted proxy.
+    pub(crate) fn from_trusted_proxy(parts: &Parts) -> Option<Self> {
+        let peer = Address::from_connect_info(&parts.extensions)?;
+        let trusted = parts.extensions.get::<TrustedProxies>()?;
+
+        trusted
+            .trusts(&peer)
+            .then(|| Self::from_request(&parts.headers, &parts.uri, Some(&peer), Some(trusted)))
+    }
+
+    /// Derives the external origin, honoring forwarding headers only from a
+    /// trusted peer.
+    fn from_request(
+        headers: &HeaderMap,
+        uri: &Uri,
+        peer: Option<&Address>,
+        trusted: Option<&TrustedProxies>,
+    ) -> Self {
+        let direct = Self::direct(headers, uri, peer);
+        let Some(trusted) = trusted.filter(|trusted| peer.is_some_and(|peer| trusted.trusts(peer)))
+        else {
+            return direct;
+        };
+
+        let elements = forwarded_elements(headers);
+        let (scheme, host, port, clients) = if elements.is_empty() {
+            (
+                last_value(headers, X_FORWARDED_PROTO),
+                last_value(headers, X_FORWARDED_HOST),
+                last_value(headers, X_FORWARDED_PORT),
+                list_values(headers, X_FORWARDED_FOR),
+            )
+        } else {
+            let last = elements.last();
+            (
+                last.and_then(|element| element.proto.clone()),
+                last.and_then(|element| element.host.clone()),
+                None,
+                elements
+                    .iter()
+                    .filter_map(|element| element.client)
+                    .collect(),
+            )
+        };
+
+        let scheme = scheme
+            .and_then(|scheme| scheme.to_ascii_lowercase().parse().ok())
+            .unwrap_or(direct.scheme);
+        let (host, host_port) = match host {
+            Some(host) => split_host(&host),
+            None => (direct.host, direct.port),
+        };
+        let port = port.and_then(|port| port.parse().ok()).or(host_port);
+        let client = if clients.is_empty() {
+            direct.client
+        } else {
+            select_client(&clients, trusted)
+        };
+        let prefix = last_value(headers, X_FORWARDED_PREFIX).filter(|prefix| !prefix.is_empty());
+
+        Self {
+            scheme,
+            host,
+            port,
+            client,
+            prefix,
+        }
+    }
+
+    /// Describes the direct connection, ignoring forwarding headers.
+    fn direct(headers: &HeaderMap, uri: &Uri, peer: Option<&Address>) -> Self {
+        let authority = headers
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| uri.authority().map(|authority| authority.as_str()));
+        let (host, port) = match authority {
+            Some(authority) => split_host(authority),
+            None => (None, None),
+        };
+        let client = match peer {
+            Some(Address::Tcp(address)) => Some(address.ip().to_canonical()),
+            _ => None,
+        };
+
+        Self {
+            scheme: uri.scheme().cloned().unwrap_or(Scheme::HTTP),
+            host,
+            port,
+            client,
+            prefix: None,
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Forwarded {
+    type Rejection = Infallible;
+
+    /// Derives the external origin of the request.
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts))
+    }
+}
+
+/// Holds the parameters of one `Forwarded` element.
+#[derive(Debug, Default)]
+struct Element {
+    /// Provides the `for` parameter, `None` inside if it names no IP address.
+    client: Option<Option<IpAddr>>,
+
+    /// Provides the `host` parameter.
+    host: Option<String>,
+
+    /// Provides the `proto` parameter.
+    proto: Option<String>,
+}
+
+/// Parses every element of every `Forwarded` header in order.
+fn forwarded_elements(headers: &HeaderMap) -> Vec<Element> {
+    headers
+        .get_all(FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| split_unquoted(value, ','))
+        .map(|element| {
+            let mut parsed = Element::default();
+            for pair in split_unquoted(element, ';') {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = unquote(value.trim());
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "for" => parsed.client = Some(parse_node(&value)),
+                    "host" => parsed.host = Some(value),
+                    "proto" => parsed.proto = Some(value),
+                    _ => {}
+                }
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Splits `value` at `separator`, ignoring separators inside quoted strings.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, character) in value.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if character == separator && !quoted => {
+                parts.push(value[start..index].trim());
+                start = index + character.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim());
+    parts.retain(|part| !part.is_empty());
+
+    parts
+}
+
+/// Removes the quotes and escapes of a quoted string.
+fn unquote(value: &str) -> String {
+    let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    else {
+        return value.to_owned();
+    };
+
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut characters = inner.chars();
+    while let Some(character) = characters.next() {
+        if character == '\\' {
+            unquoted.extend(characters.next());
+        } else {
+            unquoted.push(character);
+        }
+    }
+
+    unquoted
+}
+
+/// Parses a node identifier, ignoring any port.
+///
+/// Returns `None` for `unknown` and obfuscated identifiers.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let address = match node.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?.0,
+        None if node.matches(':').count() == 1 => node.split_once(':')?.0,
+        None => node,
+    };
+
+    address.parse::<IpAddr>().ok().map(|ip| ip.to_canonical())
+}
+
+/// Returns the comma-separated values of every header named `name` in order.
+fn values<'a>(headers: &'a HeaderMap, name: &str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Returns the last value of the headers named `name`.
+fn last_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    values(headers, name).last().map(str::to_owned)
+}
+
+/// Parses every node of the headers named `name` in order.
+fn list_values(headers: &HeaderMap, name: &str) -> Vec<Option<IpAddr>> {
+    values(headers, name).map(parse_node).collect()
+}
+
+/// Selects the client: the rightmost address not belonging to a trusted
+/// proxy, or the leftmost address if every hop is trusted.
+fn select_client(clients: &[Option<IpAddr>], trusted: &TrustedProxies) -> Option<IpAddr> {
+    clients
+        .iter()
+        .rev()
+        .find(|client| !client.is_some_and(|ip| trusted.trusts_ip(ip)))
+        .or_else(|| clients.first())
+        .copied()
+        .flatten()
+}
+
+/// Splits a `host[:port]` authority into host and port.
+fn split_host(authority: &str) -> (Option<String>, Option<u16>) {
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => match bracketed.split_once(']') {
+            Some((host, rest)) => (
+                format!("[{host}]"),
+                rest.strip_prefix(':').and_then(|port| port.parse().ok()),
+            ),
+            None => return (None, None),
+        },
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host.to_owned(), port.parse().ok()),
+            None => (authority.to_owned(), None),
+        },
+    };
+
+    ((!host.is_empty()).then(|| host.to_ascii_lowercase()), port)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::http::{uri::Scheme, HeaderMap, HeaderValue, Uri};
+    use serde::Deserialize;
+    use toml::Value;
+
+    use super::Forwarded;
+    use crate::{config::TrustedProxies, listener::Address};
+
+    /// Builds headers from name and value pairs.
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|&(name, value)| {
+                (
+                    name.parse().expect("header name should be valid"),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    /// Derives the origin of a request from `peer` trusting `10.0.0.0/8`.
+    fn forwarded(peer: &str, pairs: &[(&'static str, &'static str)]) -> Forwarded {
+        let trusted = TrustedProxies::deserialize(Value::Array(vec![Value::from("10.0.0.0/8")]))
+            .expect("trusted proxies should deserialize");
+        let peer = Address::Tcp(peer.parse::<SocketAddr>().expect("peer should parse"));
+
+        Forwarded::from_request(
+            &headers(pairs),
+            &Uri::from_static("/"),
+            Some(&peer),
+            Some(&trusted),
+        )
+    }
+
+    /// Ignores forwarding headers sent by untrusted peers.
+    #[test]
+    fn ignores_untrusted_peers() {
+        let origin = forwarded(
+            "192.0.2.7:5000",
+            &[
+                ("host", "app.internal:8080"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-for", "203.0.113.9"),
+                ("x-forwarded-prefix", "/app"),
+            ],
+        );
+
+        assert_eq!(
+            origin,
+            Forwarded {
+                scheme: Scheme::HTTP,
+                host: Some("app.internal".to_owned()),
+                port: Some(8080),
+                client: Some("192.0.2.7".parse().expect("IP should parse")),
+                prefix: None,
+            }
+        );
+    }
+
+    /// Honors `X-Forwarded-*` headers from trusted peers, skipping trusted hops.
+    #[test]
+    fn reads_x_forwarded_headers() {
+        let origin = forwarded(
+            "10.0.0.2:5000",
+            &[
+                ("host", "app.internal:8080"),
+                ("x-forwarded-proto", "HTTPS"),
+                ("x-forwarded-host", "example.com"),
+                ("x-forwarded-port", "8443"),
+                ("x-forwarded-for", "198.51.100.1, 203.0.113.9, 10.0.0.1"),
+                ("x-forwarded-prefix", "/app"),
+            ],
+        );
+
+        assert_eq!(
+            origin,
+            Forwarded {
+                scheme: Scheme::HTTPS,
+                host: Some("example.com".to_owned()),
+                port: Some(8443),
+                client: Some("203.0.113.9".parse().expect("IP should parse")),
+                prefix: Some("/app".to_owned()),
+            }
+        );
+    }
+
+    /// Prefers RFC 7239 `Forwarded` over `X-Forwarded-*` headers.
+    #[test]
+    fn reads_forwarded_header() {
+        let origin = forwarded(
+            "10.0.0.2:5000",
+            &[
+                ("x-forwarded-proto", "http"),
+                (
+                    "forwarded",
+                    r#"for="[2001:db8::17]:4711";proto=https, for=10.0.0.1;host="example.com:444";proto=https"#,
+                ),
+            ],
+        );
+
+        assert_eq!(origin.scheme, Scheme::HTTPS);
+        assert_eq!(origin.host.as_deref(), Some("example.com"));
+        assert_eq!(origin.port, Some(444));
+        assert_eq!(
+            origin.client,
+            Some("2001:db8::17".parse().expect("IP should parse"))
+        );
+
+        let hidden = forwarded("10.0.0.2:5000", &[("forwarded", "for=_hidden")]);
+        assert_eq!(hidden.client, None);
+    }
+}
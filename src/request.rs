@@ -0,0 +1,313 @@
+//! Traces HTTP requests and tags them with request IDs.
+//!
+//! [`TraceLayer`] is a Tower layer that assigns each request an `X-Request-Id`,
+//! or adopts a well-formed one sent by the reverse proxy, and echoes it in the
+//! response. The request is handled inside a `request` span recording the
+//! request ID, method, external path (including the [`Mount`] prefix), peer
+//! address, and, once the response is ready, status and latency in
+//! milliseconds. A `request finished` event is emitted at the end of each
+//! request.
+//!
+//! Handlers can read the ID through [`RequestId::current`] or an
+//! [`Extension<RequestId>`](axum::Extension) extractor; HTML error pages
+//! include it so that users can quote it when reporting problems.
+//!
+//! ```
+//! use axum::{routing::get, Router};
+//! use twelve::request::TraceLayer;
+//!
+//! let app: Router = Router::new()
+//!     .route("/", get(|| async { "hello" }))
+//!     .layer(TraceLayer::new());
+//! ```
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt::{self, Display, Formatter},
+    future::Future,
+    hash::BuildHasher,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::{HeaderName, HeaderValue, Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::{error, field::Empty, info, info_span, Instrument};
+
+use crate::{listener::Address, mount::Mount};
+
+/// Names the header carrying the request ID.
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Limits the length of request IDs adopted from the proxy.
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    /// Holds the ID of the request handled by the current task.
+    static CURRENT: RequestId;
+}
+
+/// Identifies a single HTTP request.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    /// Returns the ID of the request being handled by the current task.
+    ///
+    /// Returns `None` outside a request traced by [`TraceLayer`].
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// Returns the ID as a string.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        self.0
+            .to_str()
+            .expect("request IDs should only contain visible ASCII")
+    }
+
+    /// Adopts a proxy-supplied ID if it is short and free of separators.
+    fn adopt(value: &HeaderValue) -> Option<Self> {
+        let valid = !value.is_empty()
+            && value.len() <= MAX_LENGTH
+            && value.as_bytes().iter().all(|&byte| {
+                byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b':')
+            });
+
+        valid.then(|| Self(value.clone()))
+    }
+
+    /// Generates an ID that is unique within the process and unlikely to
+    /// collide across processes.
+    fn generate() -> Self {
+        /// Distinguishes IDs generated in the same instant.
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let random = RandomState::new().hash_one((counter, now));
+        let id = format!("{random:016x}{counter:08x}");
+
+        Self(HeaderValue::from_str(&id).expect("hexadecimal IDs should be valid header values"))
+    }
+}
+
+impl Display for RequestId {
+    /// Formats the ID.
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+/// Wraps services in [`Trace`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer;
+
+impl TraceLayer {
+    /// Constructs the layer.
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for TraceLayer {
+    type Service = Trace<S>;
+
+    /// Wraps `inner` in request tracing.
+    fn layer(&self, inner: S) -> Self::Service {
+        Trace { inner }
+    }
+}
+
+/// Traces each request handled by the inner service.
+///
+/// See the [module documentation](self) for the recorded fields.
+#[derive(Clone, Debug)]
+pub struct Trace<S> {
+    /// Handles the traced requests.
+    inner: S,
+}
+
+impl<S, B, R> Service<Request<B>> for Trace<S>
+where
+    S: Service<Request<B>, Response = Response<R>> + Clone + Send + 'static,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = Response<R>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    /// Reports whether the inner service is ready.
+    fn poll_ready(&mut self, context: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(context)
+    }
+
+    /// Handles a request inside its span and echoes its ID.
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let id = request
+            .headers()
+            .get(REQUEST_ID)
+            .and_then(RequestId::adopt)
+            .unwrap_or_else(RequestId::generate);
+        let path = match Mount::from_headers(request.headers(), request.extensions()) {
+            Ok(mount) => mount.prefixed(request.uri().path()),
+            Err(_) => request.uri().path().to_owned(),
+        };
+        let span = info_span!(
+            "request",
+            request_id = %id,
+            method = %request.method(),
+            path,
+            peer = Empty,
+            status = Empty,
+            latency_ms = Empty,
+        );
+        if let Some(peer) = Address::from_connect_info(request.extensions()) {
+            span.record("peer", tracing::field::display(peer));
+        }
+
+        request.headers_mut().insert(REQUEST_ID, id.0.clone());
+        request.extensions_mut().insert(id.clone());
+
+        // Swap in the clone that was driven to readiness by `poll_ready`.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let started = Instant::now();
+
+        Box::pin(
+            CURRENT.scope(
+                id.clone(),
+                async move {
+                    let mut response = inner.call(request).await?;
+                    let status = response.status();
+                    let span = tracing::Span::current();
+                    span.record("status", status.as_u16());
+                    span.record("latency_ms", started.elapsed().as_secs_f64() * 1000.0);
+                    if status.is_server_error() {
+                        error!("request finished");
+                    } else {
+                        info!("request finished");
+                    }
+
+                    response.headers_mut().insert(REQUEST_ID, id.0);
+                    Ok(response)
+                }
+                .instrument(span),
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{HeaderValue, Request},
+        routing::get,
+        Router,
+    };
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::{RequestId, TraceLayer, REQUEST_ID};
+
+    /// Sends a request with an optional request ID and returns the echoed ID
+    /// and the body.
+    async fn send(id: Option<&'static str>) -> (HeaderValue, String) {
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    RequestId::current()
+                        .expect("handler should run inside the request")
+                        .to_string()
+                }),
+            )
+            .layer(TraceLayer::new());
+        let mut request = Request::builder().uri("/");
+        if let Some(id) = id {
+            request = request.header(REQUEST_ID, id);
+        }
+        let response = router
+            .oneshot(
+                request
+                    .body(Body::empty())
+                    .expect("request should be valid"),
+            )
+            .await
+            .expect("router should be infallible");
+        let echoed = response
+            .headers()
+            .get(REQUEST_ID)
+            .expect("response should carry the request ID")
+            .clone();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("body should be readable")
+            .to_bytes();
+
+        (
+            echoed,
+            String::from_utf8(body.to_vec()).expect("body should be UTF-8"),
+        )
+    }
+
+    /// Adopts well-formed proxy IDs and replaces malformed ones.
+    #[tokio::test]
+    async fn propagates_or_generates_request_ids() {
+        let (echoed, body) = send(Some("proxy-1234")).await;
+        assert_eq!(echoed, "proxy-1234");
+        assert_eq!(body, "proxy-1234");
+
+        let (echoed, body) = send(Some("bad id")).await;
+        assert_ne!(echoed, "bad id");
+        assert_eq!(echoed, body.as_str());
+
+        let (first, _) = send(None).await;
+        let (second, _) = send(None).await;
+        assert_eq!(first.len(), 24);
+        assert_ne!(first, second);
+        assert_eq!(RequestId::current(), None);
+    }
+
+    /// Includes the request ID in HTML error pages.
+    #[cfg(feature = "html")]
+    #[tokio::test]
+    async fn shows_request_id_on_error_pages() {
+        use crate::page::NotFound;
+
+        let router = Router::new()
+            .fallback(NotFound::handler)
+            .layer(TraceLayer::new());
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/missing")
+                    .header(REQUEST_ID, "support-42")
+                    .body(Body::empty())
+                    .expect("request should be valid"),
+            )
+            .await
+            .expect("router should be infallible");
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("body should be readable")
+            .to_bytes();
+
+        assert!(String::from_utf8_lossy(&body).contains("<code>support-42</code>"));
+    }
+}
@@ -0,0 +1,286 @@
+//! Declares routes once for both routing and link generation.
+//!
+//! A [`Route`] is a type whose fields are the parameters of a path pattern.
+//! [`RouterExt::typed_route`] registers its pattern on an Axum router, handlers
+//! extract it with [`Path`](axum::extract::Path), and
+//! [`Mount::url_for`](crate::mount::Mount::url_for) fills the pattern back in.
+//! Renaming a path or parameter only changes the route type.
+//!
+//! Fields named in the pattern are percent-encoded into their segments, and
+//! the remaining fields become query parameters of generated links. `Path<R>`
+//! only sees the path parameters, so query fields must be `Option` or
+//! `#[serde(default)]` for it to succeed, and are read with
+//! [`Query`](axum::extract::Query) instead.
+//!
+//! A mismatch between the pattern and the fields is only detected when a link
+//! is built or a request is extracted. Call [`check`] on an example value of
+//! each route in a unit test to catch it early:
+//!
+//! ```
+//! # use serde::{Deserialize, Serialize};
+//! # use twelve::route::{self, Route};
+//! #[derive(Deserialize, Serialize)]
+//! struct Search {
+//!     category: String,
+//!     page: Option<u32>,
+//! }
+//!
+//! impl Route for Search {
+//!     const PATH: &'static str = "/search/{category}";
+//! }
+//!
+//! route::check(&Search {
+//!     category: "books".to_owned(),
+//!     page: Some(2),
+//! })?;
+//! # Ok::<(), route::Error>(())
+//! ```
+//!
+//! ```
+//! use axum::{extract::Path, response::Redirect, routing::get, Router};
+//! use serde::{Deserialize, Serialize};
+//! use twelve::{
+//!     mount::{self, Mount},
+//!     route::{Route, RouterExt},
+//! };
+//!
+//! #[derive(Deserialize, Serialize)]
+//! struct Account {
+//!     id: u64,
+//! }
+//!
+//! impl Route for Account {
+//!     const PATH: &'static str = "/accounts/{id}";
+//! }
+//!
+//! async fn show(Path(account): Path<Account>) -> String {
+//!     format!("account {}", account.id)
+//! }
+//!
+//! async fn latest(mount: Mount) -> Result<Redirect, String> {
+//!     mount
+//!         .link_for(&Account { id: 7 })
+//!         .and_then(|link| link.redirect())
+//!         .map_err(|error: mount::Error| error.to_string())
+//! }
+//!
+//! let app: Router = Router::new()
+//!     .typed_route::<Account>(get(show))
+//!     .route("/accounts/latest", get(latest));
+//! ```
+
+use axum::{routing::MethodRouter, Router};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+/// Describes a route whose fields are its path and query parameters.
+///
+/// Parameters are written as `{name}` segments, or `{*name}` for a final
+/// wildcard segment that may contain slashes, as in Axum's path syntax.
+pub trait Route: Serialize {
+    /// Provides the path pattern relative to the mount prefix.
+    const PATH: &'static str;
+}
+
+/// Registers [`Route`] types on an Axum [`Router`].
+pub trait RouterExt<S> {
+    /// Registers `method_router` for the path pattern of `R`.
+    #[must_use]
+    fn typed_route<R: Route>(self, method_router: MethodRouter<S>) -> Self;
+}
+
+impl<S> RouterExt<S> for Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    /// Registers `method_router` for the path pattern of `R`.
+    fn typed_route<R: Route>(self, method_router: MethodRouter<S>) -> Self {
+        self.route(R::PATH, method_router)
+    }
+}
+
+/// Checks that `example` fits the path pattern of its route.
+///
+/// Every parameter of the pattern must be a field, and the route must be
+/// extractable with [`Path`](axum::extract::Path) from the pattern parameters
+/// alone, so fields that only appear in the query must be optional. Intended
+/// for unit tests, with an example that sets every optional field.
+pub fn check<R>(example: &R) -> Result<(), Error>
+where
+    R: Route + DeserializeOwned,
+{
+    let encoded =
+        serde_urlencoded::to_string(example).map_err(|source| Error::Serialize { source })?;
+    let fields: Vec<(String, String)> = serde_urlencoded::from_str(&encoded)
+        .expect("serialized parameters should deserialize as pairs");
+
+    let mut parameters = Vec::new();
+    for (name, _) in R::PATH.split('/').filter_map(parameter) {
+        let field = fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .ok_or_else(|| Error::MissingParameter {
+                name: name.to_owned(),
+            })?;
+        parameters.push(field);
+    }
+
+    let encoded =
+        serde_urlencoded::to_string(parameters).map_err(|source| Error::Serialize { source })?;
+    serde_urlencoded::from_str::<R>(&encoded).map_err(|source| Error::Extract { source })?;
+
+    Ok(())
+}
+
+/// Returns the name of a `{name}` or `{*name}` pattern segment and whether it
+/// is a wildcard, or `None` for a literal segment.
+pub(crate) fn parameter(segment: &str) -> Option<(&str, bool)> {
+    let name = segment.strip_prefix('{')?.strip_suffix('}')?;
+
+    Some(match name.strip_prefix('*') {
+        Some(name) => (name, true),
+        None => (name, false),
+    })
+}
+
+/// Describes a route whose fields do not fit its path pattern.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Indicates that the route could not be serialized into parameters.
+    #[error("failed to serialize route parameters")]
+    Serialize {
+        /// Provides the underlying serialization error.
+        #[source]
+        source: serde_urlencoded::ser::Error,
+    },
+
+    /// Indicates that a parameter of the path pattern is not a field.
+    #[error("route parameter {name} is not a field")]
+    MissingParameter {
+        /// Names the missing parameter.
+        name: String,
+    },
+
+    /// Indicates that the route cannot be extracted from its path parameters,
+    /// typically because a query field is not optional.
+    #[error("route cannot be extracted from its path parameters")]
+    Extract {
+        /// Provides the underlying deserialization error.
+        #[source]
+        source: serde_urlencoded::de::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, extract::Path, http::Request, routing::get, Router};
+    use http_body_util::BodyExt;
+    use serde::{Deserialize, Serialize};
+    use tower::ServiceExt;
+
+    use super::{check, Error, Route, RouterExt};
+    use crate::mount::Mount;
+
+    /// Describes a route with a single path parameter.
+    #[derive(Deserialize, Serialize)]
+    struct Account {
+        /// Identifies the account.
+        id: u64,
+    }
+
+    impl Route for Account {
+        const PATH: &'static str = "/accounts/{id}";
+    }
+
+    /// Serves a URL generated for a route from the same route.
+    #[tokio::test]
+    async fn routes_generated_urls() {
+        let router: Router =
+            Router::new().typed_route::<Account>(get(|Path(account): Path<Account>| async move {
+                account.id.to_string()
+            }));
+        let mount = Mount {
+            script_name: None,
+            origin: None,
+        };
+        let url = mount
+            .url_for(&Account { id: 42 })
+            .expect("URL should build");
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri(url)
+                    .body(Body::empty())
+                    .expect("request should be valid"),
+            )
+            .await
+            .expect("router should be infallible");
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .expect("body should be readable")
+            .to_bytes();
+
+        assert_eq!(&body[..], b"42");
+    }
+
+    /// Describes a route with a path parameter and an optional query field.
+    #[derive(Deserialize, Serialize)]
+    struct Search {
+        /// Selects the category.
+        category: String,
+
+        /// Selects the result page.
+        page: Option<u32>,
+    }
+
+    impl Route for Search {
+        const PATH: &'static str = "/search/{*category}";
+    }
+
+    /// Describes a route whose pattern names a parameter that is not a field.
+    #[derive(Deserialize, Serialize)]
+    struct Renamed {
+        /// Identifies the account.
+        account_id: u64,
+    }
+
+    impl Route for Renamed {
+        const PATH: &'static str = "/accounts/{id}";
+    }
+
+    /// Describes a route with a required query field.
+    #[derive(Deserialize, Serialize)]
+    struct Required {
+        /// Identifies the account.
+        id: u64,
+
+        /// Selects the result page.
+        page: u32,
+    }
+
+    impl Route for Required {
+        const PATH: &'static str = "/accounts/{id}";
+    }
+
+    /// Accepts routes that fit their patterns and rejects the others.
+    #[test]
+    fn checks_routes() {
+        check(&Account { id: 7 }).expect("account route should fit");
+        check(&Search {
+            category: "books/new".to_owned(),
+            page: Some(2),
+        })
+        .expect("search route should fit");
+
+        assert!(matches!(
+            check(&Renamed { account_id: 7 }),
+            Err(Error::MissingParameter { name }) if name == "id"
+        ));
+        assert!(matches!(
+            check(&Required { id: 7, page: 2 }),
+            Err(Error::Extract { .. })
+        ));
+    }
+}
@@ -4,6 +4,19 @@
 //! arrives. Registration failures are logged and ignored. `SIGQUIT` retains
 //! its default behavior.
 //!
+//! [`Shutdown`] is a cloneable handle for background tasks. It is triggered by
+//! the first signal once [`Shutdown::listen_for_signals`] has been called, or
+//! programmatically through [`Shutdown::trigger`], and can be awaited, polled,
+//! or passed to `with_graceful_shutdown`.
+//!
+//! [`Drain`] bounds the time spent waiting for open connections: triggering its
+//! [`Shutdown`] handle starts draining, connections still open after the drain
+//! timeout are aborted, and a second signal exits the process immediately. A
+//! programmatic trigger does not count as a signal.
+//!
+//! [`Supervisor`] owns named background tasks, logs their failures, and waits
+//! for them once the server has drained.
+//!
 //! ```no_run
 //! use axum::Router;
 //! use tokio::net::TcpListener;
@@ -15,12 +28,94 @@
 //!         .await
 //! }
 //! ```
+//!
+//! ```no_run
+//! use axum::Router;
+//! use tokio::net::TcpListener;
+//! use twelve::shutdown::Shutdown;
+//!
+//! async fn serve(listener: TcpListener) -> std::io::Result<()> {
+//!     let shutdown = Shutdown::new();
+//!     shutdown.listen_for_signals();
+//!
+//!     let worker = shutdown.clone();
+//!     tokio::spawn(async move {
+//!         worker.wait().await;
+//!         // Flush buffered work before the process exits.
+//!     });
+//!
+//!     axum::serve(listener, Router::new())
+//!         .with_graceful_shutdown(shutdown.wait())
+//!         .await
+//! }
+//! ```
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use axum::Router;
+//! use twelve::{listener::Listener, shutdown::Drain};
+//!
+//! async fn serve(listener: Listener) -> std::io::Result<()> {
+//!     let drain = Drain::new(Duration::from_secs(25));
+//!     let listener = drain.track(listener);
+//!     drain
+//!         .run(|started| axum::serve(listener, Router::new()).with_graceful_shutdown(started))
+//!         .await
+//! }
+//! ```
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use axum::Router;
+//! use twelve::{
+//!     listener::Listener,
+//!     shutdown::{Drain, Supervisor},
+//! };
+//!
+//! async fn serve(listener: Listener) -> std::io::Result<()> {
+//!     let drain = Drain::new(Duration::from_secs(25));
+//!     let mut supervisor = Supervisor::new(drain.shutdown().clone(), Duration::from_secs(5));
+//!
+//!     let shutdown = drain.shutdown().clone();
+//!     supervisor.spawn_critical("flush", async move {
+//!         shutdown.wait().await;
+//!         // Flush buffered work before the process exits.
+//!         Ok::<_, std::io::Error>(())
+//!     });
+//!
+//!     let listener = drain.track(listener);
+//!     let served = drain
+//!         .run(|started| axum::serve(listener, Router::new()).with_graceful_shutdown(started))
+//!         .await;
+//!     supervisor.join().await;
+//!     served
+//! }
+//! ```
 
-use std::future::{pending, Future};
+use std::{
+    future::{pending, Future},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use tokio::signal::unix::{signal as register_unix_signal, Signal, SignalKind};
+use tokio::{
+    signal::unix::{signal as register_unix_signal, Signal, SignalKind},
+    sync::watch,
+};
 use tracing::{error, info};
 
+pub use self::{
+    drain::{Drain, DrainStarted, Tracked, TrackedIo},
+    supervisor::Supervisor,
+};
+
+mod drain;
+mod supervisor;
+
 /// Registers conventional termination handlers and waits for either signal.
 ///
 /// Registration failures are logged and omitted from the returned waiter.
@@ -30,16 +125,179 @@ use tracing::{error, info};
 ///
 /// Panics if called outside a Tokio runtime with signal support.
 pub fn signal() -> impl Future<Output = ()> {
-    let mut terminate = register_signal(SignalKind::terminate(), "SIGTERM");
-    let mut interrupt = register_signal(SignalKind::interrupt(), "SIGINT");
+    let mut signals = Signals::register();
 
     async move {
+        signals.recv().await;
+    }
+}
+
+/// Signals process shutdown to every task holding a clone.
+///
+/// Created once at startup and cloned into background tasks. The first
+/// trigger, either programmatic or the first termination signal, is observed
+/// by every clone. A second termination signal requests an immediate exit,
+/// which [`Drain`] honors.
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown {
+    /// Holds the state shared by all clones.
+    inner: Arc<Inner>,
+}
+
+/// Holds the state shared by [`Shutdown`] clones.
+#[derive(Debug)]
+struct Inner {
+    /// Publishes the shutdown state.
+    state: watch::Sender<State>,
+
+    /// Records whether signal handlers have been installed.
+    listening: AtomicBool,
+}
+
+impl Default for Inner {
+    /// Constructs an untriggered state.
+    fn default() -> Self {
+        Self {
+            state: watch::Sender::new(State::default()),
+            listening: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Describes the progress of shutdown.
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+    /// Records that shutdown has started.
+    triggered: bool,
+
+    /// Counts the termination signals received.
+    signals: u32,
+
+    /// Records the signal that demanded an immediate exit.
+    forced: Option<SignalKind>,
+}
+
+impl Shutdown {
+    /// Constructs an untriggered handle.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Triggers the handle on `SIGTERM` or `SIGINT`.
+    ///
+    /// Handlers are registered once per handle and its clones; later calls
+    /// have no effect. Registration failures are logged and ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime with signal support.
+    pub fn listen_for_signals(&self) {
+        if self.inner.listening.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let mut signals = Signals::register();
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let kind = signals.recv().await;
+                shutdown.receive(kind);
+            }
+        });
+    }
+
+    /// Starts shutdown, waking every waiting task.
+    ///
+    /// Triggering an already triggered handle has no effect.
+    pub fn trigger(&self) {
+        let triggered = self.inner.state.send_if_modified(|state| {
+            let modified = !state.triggered;
+            state.triggered = true;
+            modified
+        });
+        if triggered {
+            info!("shutdown triggered");
+        }
+    }
+
+    /// Reports whether shutdown has started.
+    #[must_use]
+    pub fn is_triggered(&self) -> bool {
+        self.inner.state.borrow().triggered
+    }
+
+    /// Waits until shutdown has started.
+    ///
+    /// The returned future does not borrow the handle, so it can be passed to
+    /// [`axum::serve::Serve::with_graceful_shutdown`].
+    pub fn wait(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut state = self.inner.state.subscribe();
+
+        async move {
+            let _ = state.wait_for(|state| state.triggered).await;
+        }
+    }
+
+    /// Records a termination signal.
+    ///
+    /// The first signal triggers shutdown unless it has already been triggered
+    /// programmatically; the second signal demands an immediate exit.
+    fn receive(&self, kind: SignalKind) {
+        self.inner.state.send_modify(|state| {
+            state.signals = state.signals.saturating_add(1);
+            state.triggered = true;
+            if state.signals > 1 {
+                state.forced.get_or_insert(kind);
+            }
+        });
+    }
+
+    /// Waits until a signal demands an immediate exit.
+    fn forced(&self) -> impl Future<Output = SignalKind> + Send + 'static {
+        let mut state = self.inner.state.subscribe();
+
+        async move {
+            let forced = match state.wait_for(|state| state.forced.is_some()).await {
+                Ok(state) => state.forced,
+                Err(_) => None,
+            };
+            match forced {
+                Some(kind) => kind,
+                None => pending().await,
+            }
+        }
+    }
+}
+
+/// Holds the conventional termination handlers.
+struct Signals {
+    /// Receives `SIGTERM` if registration succeeded.
+    terminate: Option<Signal>,
+
+    /// Receives `SIGINT` if registration succeeded.
+    interrupt: Option<Signal>,
+}
+
+impl Signals {
+    /// Registers both handlers, logging registration failures.
+    fn register() -> Self {
+        Self {
+            terminate: register_signal(SignalKind::terminate(), "SIGTERM"),
+            interrupt: register_signal(SignalKind::interrupt(), "SIGINT"),
+        }
+    }
+
+    /// Waits for either signal, logs it, and returns the received kind.
+    async fn recv(&mut self) -> SignalKind {
         tokio::select! {
-            _ = receive_signal(&mut terminate) => {
+            _ = receive_signal(&mut self.terminate) => {
                 info!(signal = "SIGTERM", "shutdown signal received");
+                SignalKind::terminate()
             }
-            _ = receive_signal(&mut interrupt) => {
+            _ = receive_signal(&mut self.interrupt) => {
                 info!(signal = "SIGINT", "shutdown signal received");
+                SignalKind::interrupt()
             }
         }
     }
@@ -65,3 +323,64 @@ async fn receive_signal(signal: &mut Option<Signal>) {
         None => pending().await,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{signal::unix::SignalKind, time};
+
+    use super::Shutdown;
+
+    /// Wakes every clone when triggered programmatically.
+    #[tokio::test]
+    async fn triggers_every_clone() {
+        let shutdown = Shutdown::new();
+        let worker = shutdown.clone();
+        let waiting = tokio::spawn(worker.wait());
+
+        assert!(!worker.is_triggered());
+        shutdown.trigger();
+        shutdown.trigger();
+
+        assert!(worker.is_triggered());
+        time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .expect("waiting task should wake")
+            .expect("waiting task should not panic");
+        time::timeout(Duration::from_secs(5), worker.wait())
+            .await
+            .expect("wait should resolve after triggering");
+    }
+
+    /// Demands an immediate exit on the second signal.
+    #[tokio::test]
+    async fn forces_on_signal_after_trigger() {
+        let shutdown = Shutdown::new();
+        shutdown.receive(SignalKind::terminate());
+        assert!(shutdown.is_triggered());
+        assert!(shutdown.inner.state.borrow().forced.is_none());
+
+        shutdown.receive(SignalKind::interrupt());
+        let kind = time::timeout(Duration::from_secs(5), shutdown.forced())
+            .await
+            .expect("second signal should force an exit");
+
+        assert_eq!(kind, SignalKind::interrupt());
+    }
+
+    /// Does not force an exit on the first signal after a programmatic trigger.
+    #[test]
+    fn counts_signals_apart_from_trigger() {
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+        shutdown.receive(SignalKind::terminate());
+        assert!(shutdown.inner.state.borrow().forced.is_none());
+
+        shutdown.receive(SignalKind::terminate());
+        assert_eq!(
+            shutdown.inner.state.borrow().forced,
+            Some(SignalKind::terminate())
+        );
+    }
+}
@@ -0,0 +1,590 @@
+//! Tracks open connections and bounds the time spent draining them.
+
+use std::{
+    future::{Future, IntoFuture},
+    io,
+    pin::Pin,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use axum::{
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener as AxumListener},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    signal::unix::SignalKind,
+    sync::oneshot,
+    time,
+};
+use tracing::{info, warn};
+
+use super::Shutdown;
+use crate::listener::{Address, Endpoint, Listener, Listeners};
+
+/// Coordinates draining open connections after shutdown is triggered.
+///
+/// Triggering the [`Shutdown`] handle, for example with the first `SIGTERM` or
+/// `SIGINT`, stops accepting connections and waits for open ones to finish.
+/// Connections still open after the drain timeout are aborted. A second
+/// signal exits the process immediately with the conventional `128 + signal`
+/// status.
+///
+/// With a [readiness grace period](Drain::readiness_grace), the server keeps
+/// accepting connections for a while after the trigger, so that load balancers
+/// observe the failing [`Health`](crate::health::Health) readiness probe and
+/// stop routing traffic before the listener closes.
+#[derive(Debug)]
+pub struct Drain {
+    /// Bounds the time spent waiting for open connections.
+    timeout: Duration,
+
+    /// Delays draining after the trigger while readiness fails.
+    readiness_grace: Duration,
+
+    /// Starts draining when triggered.
+    shutdown: Shutdown,
+
+    /// Tracks connections accepted through [`Drain::track`].
+    registry: Arc<Registry>,
+}
+
+impl Drain {
+    /// Constructs a coordinator with the given drain timeout.
+    #[must_use]
+    pub fn new(timeout: Duration) -> Self {
+        Self::with_shutdown(timeout, Shutdown::new())
+    }
+
+    /// Constructs a coordinator that drains when `shutdown` is triggered.
+    #[must_use]
+    pub fn with_shutdown(timeout: Duration, shutdown: Shutdown) -> Self {
+        Self {
+            timeout,
+            readiness_grace: Duration::ZERO,
+            shutdown,
+            registry: Arc::default(),
+        }
+    }
+
+    /// Sets the time to keep accepting connections after shutdown is
+    /// triggered and before draining starts.
+    ///
+    /// Readiness fails as soon as shutdown is triggered, so this should exceed
+    /// the interval at which load balancers probe it. Defaults to zero.
+    #[must_use]
+    pub fn readiness_grace(mut self, grace: Duration) -> Self {
+        self.readiness_grace = grace;
+        self
+    }
+
+    /// Returns the handle that starts draining.
+    #[must_use]
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    /// Wraps a listener so that its connections are counted and can be aborted.
+    #[must_use]
+    pub fn track<L>(&self, listener: L) -> Tracked<L>
+    where
+        L: AxumListener,
+    {
+        Tracked {
+            inner: listener,
+            registry: Arc::clone(&self.registry),
+        }
+    }
+
+    /// Returns the number of tracked connections that are still open.
+    #[must_use]
+    pub fn open_connections(&self) -> usize {
+        self.registry.count()
+    }
+
+    /// Serves until shutdown is triggered and the connections drain.
+    ///
+    /// Installs the signal handlers of the [`Shutdown`] handle. `serve`
+    /// receives the future that resolves when draining starts and is expected
+    /// to pass it to
+    /// [`with_graceful_shutdown`](axum::serve::Serve::with_graceful_shutdown).
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime with signal support.
+    pub async fn run<F, S>(self, serve: F) -> io::Result<()>
+    where
+        F: FnOnce(DrainStarted) -> S,
+        S: IntoFuture<Output = io::Result<()>>,
+    {
+        self.shutdown.listen_for_signals();
+
+        match self.drive(serve).await {
+            Outcome::Served(result) => result,
+            Outcome::Forced(kind) => process::exit(128 + kind.as_raw_value()),
+        }
+    }
+
+    /// Drives `serve` through the drain stages.
+    async fn drive<F, S>(self, serve: F) -> Outcome
+    where
+        F: FnOnce(DrainStarted) -> S,
+        S: IntoFuture<Output = io::Result<()>>,
+    {
+        let (start, started) = oneshot::channel();
+        let serve = serve(DrainStarted(started)).into_future();
+        tokio::pin!(serve);
+        let forced = self.shutdown.forced();
+        tokio::pin!(forced);
+
+        tokio::select! {
+            result = &mut serve => return Outcome::Served(result),
+            () = self.shutdown.wait() => {}
+        }
+
+        if !self.readiness_grace.is_zero() {
+            info!(
+                grace = ?self.readiness_grace,
+                "failing readiness before draining connections"
+            );
+            tokio::select! {
+                result = &mut serve => return Outcome::Served(result),
+                () = time::sleep(self.readiness_grace) => {}
+                kind = &mut forced => return self.force(kind),
+            }
+        }
+
+        info!(
+            connections = self.registry.count(),
+            timeout = ?self.timeout,
+            "draining open connections"
+        );
+        let _ = start.send(());
+
+        tokio::select! {
+            result = &mut serve => {
+                info!("open connections drained");
+                return Outcome::Served(result);
+            }
+            () = time::sleep(self.timeout) => {}
+            kind = &mut forced => return self.force(kind),
+        }
+
+        warn!(
+            connections = self.registry.count(),
+            "drain timeout elapsed; aborting open connections"
+        );
+        self.registry.abort();
+
+        tokio::select! {
+            result = &mut serve => Outcome::Served(result),
+            kind = &mut forced => self.force(kind),
+        }
+    }
+
+    /// Reports a forced exit after a second signal.
+    fn force(&self, kind: SignalKind) -> Outcome {
+        warn!(
+            connections = self.registry.count(),
+            "second shutdown signal received; exiting immediately"
+        );
+        Outcome::Forced(kind)
+    }
+}
+
+/// Describes how a drained server stopped.
+#[derive(Debug)]
+enum Outcome {
+    /// Indicates that the server future completed.
+    Served(io::Result<()>),
+
+    /// Indicates that a second signal demands an immediate exit.
+    Forced(SignalKind),
+}
+
+/// Resolves when a [`Drain`] starts draining connections.
+///
+/// Also resolves if the coordinator is dropped.
+#[derive(Debug)]
+pub struct DrainStarted(oneshot::Receiver<()>);
+
+impl Future for DrainStarted {
+    type Output = ();
+
+    /// Waits for the start of draining.
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(context).map(|_| ())
+    }
+}
+
+/// Tracks the open connections of a listener.
+#[derive(Debug, Default)]
+struct Registry {
+    /// Records that open connections have been aborted.
+    aborted: AtomicBool,
+
+    /// Refers to the wakers of open connections.
+    slots: Mutex<Vec<Weak<Slot>>>,
+}
+
+impl Registry {
+    /// Registers a newly accepted connection.
+    fn open(&self) -> Arc<Slot> {
+        let slot = Arc::new(Slot::default());
+        let mut slots = self.lock();
+        slots.retain(|slot| slot.strong_count() > 0);
+        slots.push(Arc::downgrade(&slot));
+        slot
+    }
+
+    /// Counts the connections that are still open.
+    fn count(&self) -> usize {
+        let mut slots = self.lock();
+        slots.retain(|slot| slot.strong_count() > 0);
+        slots.len()
+    }
+
+    /// Aborts every open connection by failing its next I/O operation.
+    fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        for slot in self.lock().iter().filter_map(Weak::upgrade) {
+            slot.wake();
+        }
+    }
+
+    /// Locks the connection list, tolerating poisoning.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Weak<Slot>>> {
+        self.slots.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+/// Holds the waker of an open connection.
+#[derive(Debug, Default)]
+struct Slot {
+    /// Wakes the task driving the connection.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Slot {
+    /// Records the waker of the task driving the connection.
+    fn register(&self, waker: &Waker) {
+        let mut slot = self.waker.lock().unwrap_or_else(|error| error.into_inner());
+        if !slot
+            .as_ref()
+            .is_some_and(|current| current.will_wake(waker))
+        {
+            *slot = Some(waker.clone());
+        }
+    }
+
+    /// Wakes the task driving the connection.
+    fn wake(&self) {
+        let waker = self
+            .waker
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Counts the connections accepted by a listener.
+///
+/// Constructed by [`Drain::track`].
+#[derive(Debug)]
+pub struct Tracked<L> {
+    /// Holds the wrapped listener.
+    inner: L,
+
+    /// Tracks accepted connections.
+    registry: Arc<Registry>,
+}
+
+impl<L> AxumListener for Tracked<L>
+where
+    L: AxumListener,
+{
+    type Addr = L::Addr;
+    type Io = TrackedIo<L::Io>;
+
+    /// Accepts and registers a connection.
+    #[inline]
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let (io, address) = self.inner.accept().await;
+        let io = TrackedIo {
+            inner: io,
+            slot: self.registry.open(),
+            registry: Arc::clone(&self.registry),
+        };
+        (io, address)
+    }
+
+    /// Returns the wrapped listener's local address.
+    #[inline]
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+impl Connected<IncomingStream<'_, Tracked<Listener>>> for Address {
+    /// Reports the peer of a connection.
+    fn connect_info(stream: IncomingStream<'_, Tracked<Listener>>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
+impl Connected<IncomingStream<'_, Tracked<Listeners>>> for Address {
+    /// Reports the peer of a connection.
+    fn connect_info(stream: IncomingStream<'_, Tracked<Listeners>>) -> Self {
+        stream.remote_addr().peer.clone()
+    }
+}
+
+impl Connected<IncomingStream<'_, Tracked<Listeners>>> for Endpoint {
+    /// Reports the peer and accepting listener of a connection.
+    fn connect_info(stream: IncomingStream<'_, Tracked<Listeners>>) -> Self {
+        stream.remote_addr().clone()
+    }
+}
+
+/// Provides a counted connection that fails once aborted.
+#[derive(Debug)]
+pub struct TrackedIo<I> {
+    /// Holds the wrapped connection.
+    inner: I,
+
+    /// Keeps the connection counted while it is open.
+    slot: Arc<Slot>,
+
+    /// Reports whether open connections have been aborted.
+    registry: Arc<Registry>,
+}
+
+impl<I> TrackedIo<I> {
+    /// Registers the current task and reports an abort as an error.
+    ///
+    /// The waker is recorded before the flag is read, so an abort racing with
+    /// this poll still wakes the task.
+    fn check(&self, context: &Context<'_>) -> io::Result<()> {
+        self.slot.register(context.waker());
+        if self.registry.aborted.load(Ordering::SeqCst) {
+            Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "connection aborted after drain timeout",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<I> AsyncRead for TrackedIo<I>
+where
+    I: AsyncRead + Unpin,
+{
+    /// Attempts to read bytes unless the connection was aborted.
+    #[inline]
+    fn poll_read(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+        buffer: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.check(context)?;
+        Pin::new(&mut this.inner).poll_read(context, buffer)
+    }
+}
+
+impl<I> AsyncWrite for TrackedIo<I>
+where
+    I: AsyncWrite + Unpin,
+{
+    /// Attempts to write bytes unless the connection was aborted.
+    #[inline]
+    fn poll_write(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+        buffer: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.check(context)?;
+        Pin::new(&mut this.inner).poll_write(context, buffer)
+    }
+
+    /// Attempts to flush the connection unless it was aborted.
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.check(context)?;
+        Pin::new(&mut this.inner).poll_flush(context)
+    }
+
+    /// Attempts to shut down the connection.
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(context)
+    }
+
+    /// Reports whether vectored writes are supported.
+    #[inline]
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    /// Attempts to write multiple buffers unless the connection was aborted.
+    #[inline]
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+        buffers: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.check(context)?;
+        Pin::new(&mut this.inner).poll_write_vectored(context, buffers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::pending, time::Duration};
+
+    use axum::{routing::get, Router};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        signal::unix::SignalKind,
+        task::JoinHandle,
+        time,
+    };
+
+    use super::{Drain, Outcome};
+    use crate::{health::Health, shutdown::Shutdown};
+
+    /// Starts a server whose only route never responds.
+    async fn start(timeout: Duration) -> (u16, Shutdown, JoinHandle<Outcome>) {
+        start_with(Drain::new(timeout)).await
+    }
+
+    /// Starts a server with health endpoints under `drain`.
+    async fn start_with(drain: Drain) -> (u16, Shutdown, JoinHandle<Outcome>) {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("TCP listener should bind");
+        let port = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .port();
+        let router = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route("/hang", get(pending::<&'static str>))
+            .merge(Health::new(drain.shutdown().clone()).router());
+        let listener = drain.track(listener);
+        let shutdown = drain.shutdown().clone();
+        let task =
+            tokio::spawn(drain.drive(move |started| {
+                axum::serve(listener, router).with_graceful_shutdown(started)
+            }));
+
+        (port, shutdown, task)
+    }
+
+    /// Sends a request and returns the connection.
+    async fn request(port: u16, path: &str) -> TcpStream {
+        let mut client = TcpStream::connect(("127.0.0.1", port))
+            .await
+            .expect("client should connect");
+        client
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: test\r\n\r\n").as_bytes())
+            .await
+            .expect("request should be sent");
+        client
+    }
+
+    /// Completes once idle connections have drained.
+    #[tokio::test]
+    async fn drains_idle_connections() {
+        let (port, shutdown, task) = start(Duration::from_secs(30)).await;
+        let mut client = request(port, "/").await;
+        let mut response = [0; 12];
+        client
+            .read_exact(&mut response)
+            .await
+            .expect("response should arrive");
+        assert_eq!(&response, b"HTTP/1.1 200");
+
+        shutdown.trigger();
+        let outcome = time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("server should drain")
+            .expect("server task should not panic");
+
+        assert!(matches!(outcome, Outcome::Served(Ok(()))));
+    }
+
+    /// Reports unreadiness to new connections during the readiness grace period.
+    #[tokio::test]
+    async fn fails_readiness_before_draining() {
+        let drain = Drain::new(Duration::from_secs(30)).readiness_grace(Duration::from_millis(300));
+        let (port, shutdown, task) = start_with(drain).await;
+
+        shutdown.trigger();
+        let mut client = request(port, "/readyz").await;
+        let mut response = [0; 12];
+        client
+            .read_exact(&mut response)
+            .await
+            .expect("response should arrive");
+        drop(client);
+        assert_eq!(&response, b"HTTP/1.1 503");
+
+        let outcome = time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("server should drain after the grace period")
+            .expect("server task should not panic");
+        assert!(matches!(outcome, Outcome::Served(Ok(()))));
+    }
+
+    /// Aborts connections that are still open after the drain timeout.
+    #[tokio::test]
+    async fn aborts_connections_after_timeout() {
+        let (port, shutdown, task) = start(Duration::from_millis(100)).await;
+        let mut client = request(port, "/hang").await;
+        time::sleep(Duration::from_millis(50)).await;
+
+        shutdown.trigger();
+        let outcome = time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("server should abort hanging connections")
+            .expect("server task should not panic");
+        let mut buffer = Vec::new();
+        let read = client.read_to_end(&mut buffer).await;
+
+        assert!(matches!(outcome, Outcome::Served(Ok(()))));
+        assert!(read.is_err() || buffer.is_empty());
+    }
+
+    /// Exits immediately on a second signal.
+    #[tokio::test]
+    async fn forces_exit_on_second_signal() {
+        let (port, shutdown, task) = start(Duration::from_secs(30)).await;
+        let _client = request(port, "/hang").await;
+        time::sleep(Duration::from_millis(50)).await;
+
+        shutdown.receive(SignalKind::terminate());
+        shutdown.receive(SignalKind::interrupt());
+        let outcome = time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("second signal should end draining")
+            .expect("server task should not panic");
+
+        assert!(matches!(outcome, Outcome::Forced(kind) if kind == SignalKind::interrupt()));
+    }
+}
@@ -0,0 +1,237 @@
+//! Supervises named background tasks and waits for them during shutdown.
+
+use std::{any::Any, fmt::Display, future::Future, time::Duration};
+
+use tokio::{
+    task::{AbortHandle, JoinHandle, JoinSet},
+    time,
+};
+use tracing::{error, info, warn};
+
+use super::Shutdown;
+
+/// Tracks background tasks that must finish before the process exits.
+///
+/// Tasks are registered by name. A task that returns an error or panics is
+/// logged, and a task registered with [`Supervisor::spawn_critical`]
+/// additionally triggers shutdown. After the server has drained,
+/// [`Supervisor::join`] waits for every task, aborting those that do not
+/// finish within the per-task timeout.
+#[derive(Debug)]
+pub struct Supervisor {
+    /// Triggered by failing critical tasks.
+    shutdown: Shutdown,
+
+    /// Bounds the time each task may take to finish once joined.
+    timeout: Duration,
+
+    /// Holds the registered tasks in registration order.
+    tasks: Vec<Supervised>,
+}
+
+/// Holds a registered task.
+#[derive(Debug)]
+struct Supervised {
+    /// Names the task in diagnostics.
+    name: String,
+
+    /// Completes once the task's outcome has been logged.
+    monitor: JoinHandle<()>,
+
+    /// Aborts the task itself.
+    task: AbortHandle,
+}
+
+impl Supervisor {
+    /// Constructs a supervisor whose critical tasks trigger `shutdown`.
+    #[must_use]
+    pub fn new(shutdown: Shutdown, timeout: Duration) -> Self {
+        Self {
+            shutdown,
+            timeout,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Returns the handle triggered by failing critical tasks.
+    #[must_use]
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    /// Spawns a task whose failure is logged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn spawn<F, E>(&mut self, name: impl Into<String>, task: F)
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        self.supervise(name.into(), task, false);
+    }
+
+    /// Spawns a task whose failure is logged and triggers shutdown.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn spawn_critical<F, E>(&mut self, name: impl Into<String>, task: F)
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        self.supervise(name.into(), task, true);
+    }
+
+    /// Spawns a task and a monitor that logs its outcome.
+    fn supervise<F, E>(&mut self, name: String, task: F, critical: bool)
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        let task = tokio::spawn(task);
+        let abort = task.abort_handle();
+        let shutdown = self.shutdown.clone();
+        let task_name = name.clone();
+        let monitor = tokio::spawn(async move {
+            let failed = match task.await {
+                Ok(Ok(())) => {
+                    info!(task = %task_name, "background task finished");
+                    false
+                }
+                Ok(Err(error)) => {
+                    error!(task = %task_name, %error, "background task failed");
+                    true
+                }
+                Err(error) if error.is_panic() => {
+                    let payload = error.into_panic();
+                    error!(
+                        task = %task_name,
+                        panic = panic_message(payload.as_ref()),
+                        "background task panicked"
+                    );
+                    true
+                }
+                Err(_) => {
+                    warn!(task = %task_name, "background task was cancelled");
+                    false
+                }
+            };
+            if failed && critical {
+                shutdown.trigger();
+            }
+        });
+
+        self.tasks.push(Supervised {
+            name,
+            monitor,
+            task: abort,
+        });
+    }
+
+    /// Waits for every task, aborting those that exceed the per-task timeout.
+    ///
+    /// Intended to be called after the server has drained, once the tasks
+    /// have observed the triggered [`Shutdown`] handle.
+    pub async fn join(self) {
+        info!(tasks = self.tasks.len(), "waiting for background tasks");
+
+        let mut waiting = JoinSet::new();
+        for supervised in self.tasks {
+            let timeout = self.timeout;
+            waiting.spawn(async move {
+                if time::timeout(timeout, supervised.monitor).await.is_err() {
+                    warn!(
+                        task = %supervised.name,
+                        ?timeout,
+                        "background task did not finish in time; aborting"
+                    );
+                    supervised.task.abort();
+                }
+            });
+        }
+        while waiting.join_next().await.is_some() {}
+    }
+}
+
+/// Extracts a printable message from a panic payload.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string panic payload"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::pending,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use tokio::time;
+
+    use super::Supervisor;
+    use crate::shutdown::Shutdown;
+
+    /// Waits for tasks that finish after shutdown is triggered.
+    #[tokio::test]
+    async fn waits_for_tasks_to_finish() {
+        let shutdown = Shutdown::new();
+        let mut supervisor = Supervisor::new(shutdown.clone(), Duration::from_secs(5));
+        let flushed = Arc::new(AtomicBool::new(false));
+        let task_flushed = Arc::clone(&flushed);
+        let task_shutdown = shutdown.clone();
+        supervisor.spawn("flush", async move {
+            task_shutdown.wait().await;
+            task_flushed.store(true, Ordering::SeqCst);
+            Ok::<_, String>(())
+        });
+
+        shutdown.trigger();
+        supervisor.join().await;
+
+        assert!(flushed.load(Ordering::SeqCst));
+    }
+
+    /// Triggers shutdown when a critical task fails or panics.
+    #[tokio::test]
+    async fn critical_failures_trigger_shutdown() {
+        for panics in [false, true] {
+            let shutdown = Shutdown::new();
+            let mut supervisor = Supervisor::new(shutdown.clone(), Duration::from_secs(5));
+            supervisor.spawn("optional", async { Err::<(), _>("ignored") });
+            supervisor.spawn_critical("consumer", async move {
+                if panics {
+                    panic!("consumer crashed");
+                }
+                Err::<(), _>("queue closed")
+            });
+
+            time::timeout(Duration::from_secs(5), shutdown.wait())
+                .await
+                .expect("critical failure should trigger shutdown");
+            supervisor.join().await;
+        }
+    }
+
+    /// Aborts tasks that exceed the per-task timeout.
+    #[tokio::test]
+    async fn aborts_tasks_after_timeout() {
+        let mut supervisor = Supervisor::new(Shutdown::new(), Duration::from_millis(50));
+        supervisor.spawn("stuck", pending::<Result<(), String>>());
+
+        time::timeout(Duration::from_secs(5), supervisor.join())
+            .await
+            .expect("join should not wait beyond the task timeout");
+    }
+}